@@ -0,0 +1,449 @@
+//! Which keys, mouse buttons and controller buttons do what, loaded from `CONTROLS_FILE` if there is one.
+//!
+//! The file has a line per action, `action = binding, binding, ...`, and `#` starts a comment.
+//! A binding is a key (by its SDL name, like `Space` or `Left Shift`), `MouseLeft`,
+//! `MouseMiddle`, `MouseRight`, or a controller button as `Pad` and its SDL name (`Pad a`,
+//! `Pad leftshoulder`, `Pad dpup`), with any of `Ctrl+`, `Shift+` and `Alt+` in front, say
+//! `Ctrl+R`. An action in the file loses all its default bindings, the ones that aren't keep
+//! them, except for anything the file has bound to something else. An action can have more than
+//! one line but a binding can only be in the file for one action.
+//!
+//! ```text
+//! # bigger numbers for a bigger brush
+//! bigger_cursor = Right, Shift+Up
+//! clear = Ctrl+R
+//! ```
+use std::fmt;
+
+use sdl2::controller::Button;
+use sdl2::keyboard::{KeyboardState, Keycode, Mod, Scancode};
+use sdl2::mouse::{MouseButton, MouseState};
+
+use crate::gamepad::Gamepads;
+
+pub const CONTROLS_FILE: &str = "controls.cfg";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    NextMaterial,
+    PrevMaterial,
+    BiggerCursor,
+    SmallerCursor,
+    /// Left mouse, draws tiles or digs in gameplay mode.
+    Primary,
+    /// Right mouse, erases tiles or places them in gameplay mode.
+    Secondary,
+    Detonate,
+    RunLeft,
+    RunRight,
+    Jump,
+    Gameplay,
+    Clear,
+    Step,
+    Pause,
+    SandCrack,
+    GravelCrack,
+    CycleMode,
+    CycleBoundary,
+    MakeBody,
+    Integrity,
+    Checkpoint,
+    /// Back to the checkpoint, without waiting to die.
+    Respawn,
+    Help,
+    /// Lets loose a creature that runs away from players.
+    Critter,
+    /// Lets loose a creature that chases them.
+    Monster,
+    /// Throws the player's projectile at the cursor.
+    Throw,
+    NextProjectile,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::NextMaterial, Action::PrevMaterial, Action::BiggerCursor, Action::SmallerCursor,
+        Action::Primary, Action::Secondary, Action::Detonate, Action::RunLeft, Action::RunRight,
+        Action::Jump, Action::Gameplay, Action::Clear, Action::Step, Action::Pause,
+        Action::SandCrack, Action::GravelCrack, Action::CycleMode, Action::CycleBoundary,
+        Action::MakeBody, Action::Integrity, Action::Checkpoint, Action::Respawn, Action::Help,
+        Action::Critter, Action::Monster, Action::Throw, Action::NextProjectile,
+    ];
+
+    /// What it's called in the controls file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::NextMaterial    => "next_material",
+            Action::PrevMaterial    => "prev_material",
+            Action::BiggerCursor    => "bigger_cursor",
+            Action::SmallerCursor   => "smaller_cursor",
+            Action::Primary         => "primary",
+            Action::Secondary       => "secondary",
+            Action::Detonate        => "detonate",
+            Action::RunLeft         => "run_left",
+            Action::RunRight        => "run_right",
+            Action::Jump            => "jump",
+            Action::Gameplay        => "gameplay",
+            Action::Clear           => "clear",
+            Action::Step            => "step",
+            Action::Pause           => "pause",
+            Action::SandCrack       => "sand_crack",
+            Action::GravelCrack     => "gravel_crack",
+            Action::CycleMode       => "cycle_mode",
+            Action::CycleBoundary   => "cycle_boundary",
+            Action::MakeBody        => "make_body",
+            Action::Integrity       => "integrity",
+            Action::Checkpoint      => "checkpoint",
+            Action::Respawn         => "respawn",
+            Action::Help            => "help",
+            Action::Critter         => "critter",
+            Action::Monster         => "monster",
+            Action::Throw           => "throw",
+            Action::NextProjectile  => "next_projectile",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    /// What it does, for the help overlay.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::NextMaterial    => "next material",
+            Action::PrevMaterial    => "previous material",
+            Action::BiggerCursor    => "bigger cursor",
+            Action::SmallerCursor   => "smaller cursor",
+            Action::Primary         => "draw, dig in gameplay",
+            Action::Secondary       => "erase, place in gameplay",
+            Action::Detonate        => "blow up the cursor",
+            Action::RunLeft         => "run left",
+            Action::RunRight        => "run right",
+            Action::Jump            => "jump, swim",
+            Action::Gameplay        => "toggle gameplay mode",
+            Action::Clear           => "clear the world",
+            Action::Step            => "step one tick",
+            Action::Pause           => "pause",
+            Action::SandCrack       => "sand crack fixture",
+            Action::GravelCrack     => "gravel crack fixture",
+            Action::CycleMode       => "next automaton",
+            Action::CycleBoundary   => "next boundary",
+            Action::MakeBody        => "make or unmark a rigid body",
+            Action::Integrity       => "toggle structural integrity",
+            Action::Checkpoint      => "set checkpoint",
+            Action::Respawn         => "back to the checkpoint",
+            Action::Help            => "this help",
+            Action::Critter         => "spawn a critter",
+            Action::Monster         => "spawn a monster",
+            Action::Throw           => "throw at the cursor",
+            Action::NextProjectile  => "next thing to throw",
+        }
+    }
+}
+
+/// Modifier keys, either side counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mods {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Mods {
+    pub fn from_sdl(m: Mod) -> Self {
+        Self {
+            ctrl: m.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            shift: m.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            alt: m.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+
+    /// Whether everything in `other` is held in `self`.
+    fn contains(self, other: Mods) -> bool {
+        (self.ctrl || !other.ctrl) && (self.shift || !other.shift) && (self.alt || !other.alt)
+    }
+
+    fn count(self) -> usize {
+        self.ctrl as usize + self.shift as usize + self.alt as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Pad(Button),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub input: Input,
+    pub mods: Mods,
+}
+
+impl Binding {
+    const fn key(key: Keycode) -> Self {
+        Self { input: Input::Key(key), mods: Mods { ctrl: false, shift: false, alt: false } }
+    }
+
+    const fn mouse(button: MouseButton) -> Self {
+        Self { input: Input::Mouse(button), mods: Mods { ctrl: false, shift: false, alt: false } }
+    }
+
+    const fn pad(button: Button) -> Self {
+        Self { input: Input::Pad(button), mods: Mods { ctrl: false, shift: false, alt: false } }
+    }
+
+    /// The same with Ctrl held.
+    const fn ctrl(self) -> Self {
+        Self { mods: Mods { ctrl: true, ..self.mods }, ..self }
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let name = parts.pop().unwrap_or_default();
+        let mut mods = Mods::default();
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" => mods.ctrl = true,
+                "shift" => mods.shift = true,
+                "alt" => mods.alt = true,
+                _ => return Err(format!("unknown modifier '{}'", part)),
+            }
+        }
+        let lower = name.to_lowercase();
+        let input = match lower.as_str() {
+            "mouseleft" => Input::Mouse(MouseButton::Left),
+            "mousemiddle" => Input::Mouse(MouseButton::Middle),
+            "mouseright" => Input::Mouse(MouseButton::Right),
+            _ if lower.starts_with("pad ") => {
+                let button = lower["pad ".len()..].trim();
+                Input::Pad(Button::from_string(button).ok_or_else(|| format!("unknown controller button '{}'", button))?)
+            }
+            _ => Input::Key(Keycode::from_name(name).ok_or_else(|| format!("unknown key '{}'", name))?),
+        };
+        Ok(Self { input, mods })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mods.ctrl { write!(f, "Ctrl+")?; }
+        if self.mods.shift { write!(f, "Shift+")?; }
+        if self.mods.alt { write!(f, "Alt+")?; }
+        match self.input {
+            Input::Key(key) => write!(f, "{}", key.name()),
+            Input::Mouse(MouseButton::Left) => write!(f, "MouseLeft"),
+            Input::Mouse(MouseButton::Middle) => write!(f, "MouseMiddle"),
+            Input::Mouse(MouseButton::Right) => write!(f, "MouseRight"),
+            Input::Mouse(button) => write!(f, "{:?}", button),
+            Input::Pad(button) => write!(f, "Pad {}", button.string()),
+        }
+    }
+}
+
+const DEFAULT_BINDINGS: &[(Action, Binding)] = &[
+    (Action::NextMaterial,  Binding::key(Keycode::Up)),
+    (Action::PrevMaterial,  Binding::key(Keycode::Down)),
+    (Action::BiggerCursor,  Binding::key(Keycode::Right)),
+    (Action::SmallerCursor, Binding::key(Keycode::Left)),
+    (Action::Primary,       Binding::mouse(MouseButton::Left)),
+    (Action::Secondary,     Binding::mouse(MouseButton::Right)),
+    (Action::Detonate,      Binding::mouse(MouseButton::Middle)),
+    (Action::RunLeft,       Binding::key(Keycode::A)),
+    (Action::RunRight,      Binding::key(Keycode::D)),
+    (Action::Jump,          Binding::key(Keycode::Space)),
+    (Action::Gameplay,      Binding::key(Keycode::Tab)),
+    (Action::Clear,         Binding::key(Keycode::R).ctrl()),
+    (Action::Step,          Binding::key(Keycode::U)),
+    (Action::Pause,         Binding::key(Keycode::P)),
+    (Action::SandCrack,     Binding::key(Keycode::F1)),
+    (Action::GravelCrack,   Binding::key(Keycode::F2)),
+    (Action::CycleMode,     Binding::key(Keycode::M)),
+    (Action::CycleBoundary, Binding::key(Keycode::B)),
+    (Action::MakeBody,      Binding::key(Keycode::G)),
+    (Action::Integrity,     Binding::key(Keycode::I)),
+    (Action::Checkpoint,    Binding::key(Keycode::K)),
+    (Action::Respawn,       Binding::key(Keycode::R)),
+    (Action::Help,          Binding::key(Keycode::H)),
+    (Action::Critter,       Binding::key(Keycode::C)),
+    (Action::Monster,       Binding::key(Keycode::V)),
+    (Action::Throw,         Binding::key(Keycode::F)),
+    (Action::NextProjectile, Binding::key(Keycode::Q)),
+    (Action::NextMaterial,  Binding::pad(Button::DPadUp)),
+    (Action::PrevMaterial,  Binding::pad(Button::DPadDown)),
+    (Action::BiggerCursor,  Binding::pad(Button::DPadRight)),
+    (Action::SmallerCursor, Binding::pad(Button::DPadLeft)),
+    (Action::Primary,       Binding::pad(Button::RightShoulder)),
+    (Action::Secondary,     Binding::pad(Button::LeftShoulder)),
+    (Action::Detonate,      Binding::pad(Button::Y)),
+    (Action::Jump,          Binding::pad(Button::A)),
+    (Action::Gameplay,      Binding::pad(Button::Back)),
+    (Action::Pause,         Binding::pad(Button::Start)),
+    (Action::Throw,         Binding::pad(Button::X)),
+    (Action::NextProjectile, Binding::pad(Button::B)),
+];
+
+/// Which inputs drive a player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Seat {
+    /// The keyboard and mouse.
+    pub keyboard: bool,
+    /// Which controller, counting in the order they were plugged in.
+    pub pad: Option<usize>,
+}
+
+impl Seat {
+    /// The keyboard and mouse go to the first of `players` players and the controllers to the
+    /// rest. Someone playing alone gets the first controller as well.
+    pub fn for_player(player: usize, players: usize) -> Self {
+        match player {
+            0 => Self { keyboard: true, pad: (players == 1).then_some(0) },
+            _ => Self { keyboard: false, pad: Some(player - 1) },
+        }
+    }
+}
+
+pub struct Bindings {
+    bindings: Vec<(Action, Binding)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self { bindings: DEFAULT_BINDINGS.to_vec() }
+    }
+}
+
+impl Bindings {
+    /// The defaults with whatever's in the file at `path` on top, just the defaults if there's
+    /// no file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}:{}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut res = Self::default();
+        // actions the file has bound so far, which have lost their defaults
+        let mut bound = vec![];
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() { continue; }
+
+            let bad = |why: String| format!("{}: {}", n + 1, why);
+            let (name, bindings) = line.split_once('=').ok_or_else(|| bad("expected 'action = binding'".into()))?;
+            let action = Action::from_name(name.trim())
+                .ok_or_else(|| bad(format!("unknown action '{}'", name.trim())))?;
+
+            if !bound.contains(&action) {
+                res.bindings.retain(|(a, _)| *a != action);
+                bound.push(action);
+            }
+            for binding in bindings.split(',').filter(|b| !b.trim().is_empty()) {
+                let binding = Binding::parse(binding).map_err(bad)?;
+                match res.bindings.iter().find(|(a, b)| *b == binding && bound.contains(a)) {
+                    Some((a, _)) if *a == action => continue,
+                    Some((a, _)) => return Err(bad(format!("{} is already bound to {}", binding, a.name()))),
+                    None => {}
+                }
+                // whatever it did by default, it does this now
+                res.bindings.retain(|(_, b)| *b != binding);
+                res.bindings.push((action, binding));
+            }
+        }
+        Ok(res)
+    }
+
+    /// The action `input` was just pressed for, with `mods` held. When there's more than one the
+    /// one that needs the most modifiers wins, so `Ctrl+R` beats plain `R`.
+    pub fn pressed(&self, input: Input, mods: Mod) -> Option<Action> {
+        let mods = Mods::from_sdl(mods);
+        self.bindings.iter()
+            .filter(|(_, b)| b.input == input && mods.contains(b.mods))
+            .max_by_key(|(_, b)| b.mods.count())
+            .map(|(a, _)| *a)
+    }
+
+    /// Whether anything `seat` has that's bound to `action` is being held down.
+    pub fn held(&self, action: Action, seat: Seat, kbd: &KeyboardState, mouse: &MouseState, pads: &Gamepads, mods: Mod) -> bool {
+        let mods = Mods::from_sdl(mods);
+        self.bindings.iter()
+            .filter(|(a, b)| *a == action && mods.contains(b.mods))
+            .any(|(_, b)| match b.input {
+                Input::Key(key) => seat.keyboard && Scancode::from_keycode(key).is_some_and(|s| kbd.is_scancode_pressed(s)),
+                Input::Mouse(button) => seat.keyboard && mouse.is_mouse_button_pressed(button),
+                Input::Pad(button) => seat.pad.is_some_and(|pad| pads.button(pad, button)),
+            })
+    }
+
+    /// A line per action with what it's bound to, for the help overlay.
+    pub fn help(&self) -> Vec<String> {
+        Action::ALL.iter()
+            .map(|a| {
+                let keys: Vec<_> = self.bindings.iter()
+                    .filter(|(b, _)| b == a)
+                    .map(|(_, b)| b.to_string())
+                    .collect();
+                format!("{}: {}", if keys.is_empty() { "-".into() } else { keys.join(", ") }, a.describe())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(bindings: &Bindings, action: Action) -> Vec<String> {
+        bindings.bindings.iter().filter(|(a, _)| *a == action).map(|(_, b)| b.to_string()).collect()
+    }
+
+    #[test]
+    fn modifiers() {
+        let b = Binding::parse(" ctrl + Shift+A ").unwrap();
+        assert_eq!(b, Binding { input: Input::Key(Keycode::A), mods: Mods { ctrl: true, shift: true, alt: false } });
+        assert_eq!(b.to_string(), "Ctrl+Shift+A");
+        assert_eq!(Binding::parse("Alt+MouseRight").unwrap().to_string(), "Alt+MouseRight");
+        assert_eq!(Binding::parse("Pad leftshoulder").unwrap(), Binding::pad(Button::LeftShoulder));
+        assert!(Binding::parse("Hyper+A").is_err());
+    }
+
+    #[test]
+    fn most_modifiers_win() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.pressed(Input::Key(Keycode::R), Mod::NOMOD), Some(Action::Respawn));
+        assert_eq!(bindings.pressed(Input::Key(Keycode::R), Mod::LCTRLMOD), Some(Action::Clear));
+        assert_eq!(bindings.pressed(Input::Key(Keycode::R), Mod::RCTRLMOD | Mod::LSHIFTMOD), Some(Action::Clear));
+    }
+
+    #[test]
+    fn file_replaces_defaults() {
+        let bindings = Bindings::parse("# comment\n\njump = W, Shift+Space # and another\nnext_material = Space\n").unwrap();
+        assert_eq!(bound(&bindings, Action::Jump), ["W", "Shift+Space"]);
+        assert_eq!(bound(&bindings, Action::NextMaterial), ["Space"]);
+        assert_eq!(bound(&bindings, Action::Pause), ["P", "Pad start"]);
+    }
+
+    #[test]
+    fn bad_lines() {
+        for text in ["jump", "fly = W", "jump = Nope", "jump = Pad nope", "jump = W,, Ctrl+"] {
+            assert!(Bindings::parse(text).is_err(), "'{}' parsed", text);
+        }
+        assert_eq!(Bindings::parse("pause = P\njump = W\nfly = X").err(), Some("3: unknown action 'fly'".to_string()));
+    }
+
+    #[test]
+    fn duplicates() {
+        let bindings = Bindings::parse("jump = W, W\njump = X, w").unwrap();
+        assert_eq!(bound(&bindings, Action::Jump), ["W", "X"]);
+        let bindings = Bindings::parse("jump = R").unwrap();
+        assert_eq!(bound(&bindings, Action::Respawn), Vec::<String>::new());
+        assert_eq!(bindings.pressed(Input::Key(Keycode::R), Mod::NOMOD), Some(Action::Jump));
+        assert_eq!(
+            Bindings::parse("jump = W\npause = W").err(),
+            Some("2: W is already bound to jump".to_string()));
+    }
+}
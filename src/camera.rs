@@ -0,0 +1,69 @@
+//! Which part of the world is on screen. Everything drawn through `Canvas2` is in world pixels
+//! and goes through the camera on its way to the window.
+use std::time::Duration;
+
+use sdl2::rect::Rect;
+
+use crate::vec2::Vec2;
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+
+/// Closest the camera gets, however near each other the players are.
+const MAX_ZOOM          : f32 = 2.5;
+/// Room kept around the players when fitting them in, in pixels.
+const FIT_MARGIN        : f32 = 80.0;
+/// How fast the camera catches up, the bigger the quicker.
+const CAMERA_SPEED      : f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// World pixel in the middle of the screen.
+    pub centre: Vec2,
+    /// How many times bigger than the window's own size things are drawn, the whole world's in
+    /// view at 1.0.
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self { centre: Vec2(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0), zoom: 1.0 }
+    }
+}
+
+impl Camera {
+    /// The closest view with all of `rects` in it, the whole world if there aren't any.
+    pub fn fit(rects: &[Rect]) -> Self {
+        let Some(first) = rects.first() else { return Self::default(); };
+        let bounds = rects.iter().fold(*first, |a, b| a.union(*b));
+        let (w, h) = (bounds.w as f32 + FIT_MARGIN * 2.0, bounds.h as f32 + FIT_MARGIN * 2.0);
+        let zoom = (WINDOW_WIDTH as f32 / w).min(WINDOW_HEIGHT as f32 / h).clamp(1.0, MAX_ZOOM);
+        Self { centre: bounds.center().into(), zoom }.clamped()
+    }
+
+    /// Moves part of the way towards `target`, more the longer `elapsed` is, so it eases in
+    /// whatever the frame rate.
+    pub fn follow(&mut self, target: Camera, elapsed: Duration) {
+        let t = 1.0 - (-CAMERA_SPEED * elapsed.as_secs_f32()).exp();
+        self.centre = Vec2::lerp(&self.centre, &target.centre, t);
+        self.zoom = crate::vec2::lerp(self.zoom, target.zoom, t);
+        *self = self.clamped();
+    }
+
+    /// Keeps the view from going past the edges of the world.
+    fn clamped(self) -> Self {
+        let (half_w, half_h) = (WINDOW_WIDTH as f32 / 2.0 / self.zoom, WINDOW_HEIGHT as f32 / 2.0 / self.zoom);
+        let centre = Vec2(
+            self.centre.0.clamp(half_w, WINDOW_WIDTH as f32 - half_w),
+            self.centre.1.clamp(half_h, WINDOW_HEIGHT as f32 - half_h));
+        Self { centre, ..self }
+    }
+
+    /// Where world pixel `p` is on screen, at the window's starting size.
+    pub fn to_screen(self, p: Vec2) -> Vec2 {
+        (p - self.centre) * self.zoom + Vec2(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0)
+    }
+
+    /// The other way round from `to_screen`.
+    pub fn to_world(self, p: Vec2) -> Vec2 {
+        (p - Vec2(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0)) * (1.0 / self.zoom) + self.centre
+    }
+}
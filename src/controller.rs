@@ -0,0 +1,146 @@
+//! Turns what a player wants to do into what the `Player` does, so it doesn't matter whether
+//! it's the keyboard, a gamepad, a replay or some script deciding.
+use crate::grid::Grid;
+use crate::player::{Player, MAXJUMP, PLAYER_HORIZONTAL_MOVEMENT_SPEED};
+
+/// Everything a player can ask for in one tick.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Actions {
+    /// Which way to run, -1.0 is all the way left and 1.0 all the way right.
+    pub run: f32,
+    /// Jump is held down, the longer it's held the higher the jump.
+    pub jump: bool,
+    /// Jump was pressed since the last tick.
+    pub jump_pressed: bool,
+    /// Tile to keep digging at.
+    pub dig: Option<(usize, usize)>,
+    /// Tile to put the selected inventory tile down at.
+    pub place: Option<(usize, usize)>,
+}
+
+/// The run up and jump state of a player, fed `Actions` every player tick.
+#[derive(Default, Debug)]
+pub struct PlayerController {
+    /// Current running speed, builds up while running and dies down after.
+    run: f32,
+    /// What's left of the jump, added on every tick jump is still held.
+    jump: f32,
+}
+
+impl PlayerController {
+    pub fn apply(&mut self, player: &mut Player, grid: &mut Grid, actions: &Actions) {
+        player.move_x(self.run * actions.run.clamp(-1.0, 1.0));
+
+        if actions.run != 0.0 {
+            if self.run == 0.0 {
+                self.run = 2.0;
+            }
+            self.run = self.run * 2.5;
+            if self.run > PLAYER_HORIZONTAL_MOVEMENT_SPEED {
+                self.run = PLAYER_HORIZONTAL_MOVEMENT_SPEED;
+            }
+        }
+        else {
+            self.run *= 0.2;
+            if self.run < 0.001 {
+                self.run = 0.0;
+            }
+        }
+
+        if actions.jump_pressed {
+            player.buffer_jump();
+        }
+        // a jump pressed a little early or a little late off an edge still counts
+        if player.take_jump() {
+            self.jump = MAXJUMP;
+            player.move_y(-(MAXJUMP + 2.0));
+        }
+        // strokes instead of jumps in water
+        if actions.jump && player.swim(grid) {
+            self.jump = 0.0;
+        }
+        else if actions.jump {
+            player.move_y(-self.jump);
+            self.jump = self.jump / 2.0;
+            if self.jump < 0.001 {
+                self.jump = 0.0;
+            }
+        }
+        // letting go cuts the jump short
+        else {
+            self.jump = 0.0;
+        }
+
+        match actions.dig {
+            Some((x, y)) => player.dig(grid, x, y),
+            None => player.stop_digging(),
+        }
+        if let Some((x, y)) = actions.place {
+            player.place(grid, x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{TILE_HEIGHT, TILE_WIDTH};
+    use crate::{entity, AIR, STONE};
+
+    const FLOOR: usize = 15;
+
+    /// A player standing on a stone floor, with time to land on it.
+    fn setup() -> (Player, PlayerController, Grid) {
+        let mut grid = Grid::new(30, 20).unwrap();
+        for x in 0..30 {
+            grid.set(x, FLOOR, STONE, 1).unwrap();
+        }
+        let mut player = Player::new(10.0 * TILE_WIDTH as f32, (FLOOR - 3) as f32 * TILE_HEIGHT as f32, (0, 0, 0));
+        let mut controller = PlayerController::default();
+        run(&mut player, &mut controller, &mut grid, &Actions::default(), 20);
+        (player, controller, grid)
+    }
+
+    /// Feeds the player `actions` for `ticks` player ticks.
+    fn run(player: &mut Player, controller: &mut PlayerController, grid: &mut Grid, actions: &Actions, ticks: usize) {
+        for _ in 0..ticks {
+            controller.apply(player, grid, actions);
+            entity::tick(std::slice::from_mut(player), &mut vec![], grid);
+        }
+    }
+
+    #[test]
+    fn runs() {
+        let (mut player, mut controller, mut grid) = setup();
+        let start = player.body.rect();
+        run(&mut player, &mut controller, &mut grid, &Actions { run: 1.0, ..Default::default() }, 10);
+        assert!(player.body.rect().x > start.x + TILE_WIDTH as i32, "only got from {:?} to {:?}", start, player.body.rect());
+        assert_eq!(player.body.rect().bottom(), (FLOOR * TILE_HEIGHT) as i32);
+        run(&mut player, &mut controller, &mut grid, &Actions { run: -1.0, ..Default::default() }, 20);
+        assert!(player.body.rect().x < start.x);
+    }
+
+    #[test]
+    fn jumps() {
+        let (mut player, mut controller, mut grid) = setup();
+        let ground = (FLOOR * TILE_HEIGHT) as i32;
+        assert_eq!(player.body.rect().bottom(), ground);
+        run(&mut player, &mut controller, &mut grid, &Actions { jump: true, jump_pressed: true, ..Default::default() }, 1);
+        run(&mut player, &mut controller, &mut grid, &Actions { jump: true, ..Default::default() }, 3);
+        assert!(player.body.rect().bottom() < ground - TILE_HEIGHT as i32, "only got up to {}", player.body.rect().bottom());
+        run(&mut player, &mut controller, &mut grid, &Actions::default(), 30);
+        assert_eq!(player.body.rect().bottom(), ground);
+    }
+
+    #[test]
+    fn digs() {
+        let (mut player, mut controller, mut grid) = setup();
+        let under = (player.body.centre().0 as usize / TILE_WIDTH, FLOOR);
+        let dig = Actions { dig: Some(under), ..Default::default() };
+        run(&mut player, &mut controller, &mut grid, &dig, 5);
+        assert_eq!(grid.index_at(under.0, under.1), Some(STONE));
+        run(&mut player, &mut controller, &mut grid, &dig, 40);
+        assert_eq!(grid.index_at(under.0, under.1), Some(AIR));
+        assert_eq!(player.inventory.take_selected(), Some(STONE));
+    }
+}
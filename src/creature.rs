@@ -0,0 +1,181 @@
+//! Things that wander about on their own. They walk the way `Grid::find_path` tells them, keeping
+//! out of water and fire, and when a player comes near the timid ones run off and the hostile
+//! ones give chase.
+use crate::entity::{Body, Entity};
+use crate::grid::path::Stride;
+use crate::grid::{Grid, TILE_HEIGHT, TILE_WIDTH};
+use crate::rng::Rng;
+use crate::vec2::Vec2;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+const CREATURE_WIDTH    : u32 = TILE_WIDTH as u32;
+const CREATURE_HEIGHT   : u32 = TILE_HEIGHT as u32;
+/// Floats in water like the player, not that they go in on purpose.
+const CREATURE_DENSITY  : f32 = 800.0;
+/// What the pathfinding thinks a creature can manage, it has to match `CREATURE_JUMP` and
+/// `CREATURE_RUN`.
+const CREATURE_STRIDE   : Stride = Stride { w: 1, h: 1, jump: 2, leap: 2, fall: 6 };
+/// Pushed along by this much every tick it's walking, in pixels per tick.
+const CREATURE_RUN      : f32 = 2.0;
+const CREATURE_JUMP     : f32 = 18.0;
+
+/// How close a player has to be to be noticed, in pixels.
+const SIGHT             : f32 = TILE_WIDTH as f32 * 12.0;
+/// How far a timid creature tries to get from a player, in tiles.
+const FLEE_DISTANCE     : i32 = 12;
+/// Furthest away a creature wanders to in one go, in tiles.
+const WANDER_RANGE      : i32 = 10;
+/// Odds per tick of setting off somewhere when it's got nowhere to go.
+const WANDER_CHANCE     : f32 = 0.02;
+/// Ticks between working out the way again when there's a player about, who won't stay put.
+const REPATH_TICKS      : u32 = 10;
+/// Health a player loses per tick touching a hostile creature.
+const BITE_DAMAGE       : f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Temper {
+    /// Runs away from players.
+    Timid,
+    /// Runs at them and bites.
+    Hostile,
+}
+
+#[derive(Debug)]
+pub struct Creature {
+    body: Body,
+    temper: Temper,
+    /// Cells still to go through, see `Grid::find_path`.
+    path: Vec<(i32, i32)>,
+    /// Last cell on the path it got to, where the move to the next one starts from.
+    last: (i32, i32),
+    /// Ticks until the way gets worked out again while there's a player about.
+    repath: u32,
+    rng: Rng,
+    /// Fell into the void.
+    lost: bool,
+}
+
+impl Creature {
+    pub fn new(x: f32, y: f32, temper: Temper, rng: Rng) -> Self {
+        Self {
+            body: Body::new(Vec2(x, y), CREATURE_WIDTH, CREATURE_HEIGHT, CREATURE_DENSITY),
+            temper,
+            path: vec![],
+            last: (0, 0),
+            repath: 0,
+            rng,
+            lost: false,
+        }
+    }
+
+    /// The cell a rect's feet are in, its middle along the bottom.
+    fn feet(rect: Rect) -> (i32, i32) {
+        (rect.center().x().div_euclid(TILE_WIDTH as i32), (rect.bottom() - 1).div_euclid(TILE_HEIGHT as i32))
+    }
+
+    /// Where the creature wants to go given where the nearest player is, if anywhere.
+    fn destination(&mut self, player: Option<Rect>) -> Option<(i32, i32)> {
+        let feet = Self::feet(self.body.rect());
+        match (player, self.temper) {
+            (Some(player), Temper::Hostile) => Some(Self::feet(player)),
+            (Some(player), Temper::Timid) => {
+                let away = if Self::feet(player).0 > feet.0 { -1 } else { 1 };
+                Some((feet.0 + away * FLEE_DISTANCE, feet.1))
+            }
+            (None, _) if self.path.is_empty() && self.rng.chance(WANDER_CHANCE) => {
+                let mut offset = || self.rng.below(WANDER_RANGE as usize * 2 + 1) as i32 - WANDER_RANGE;
+                Some((feet.0 + offset(), feet.1 + offset() / 2))
+            }
+            (None, _) => None,
+        }
+    }
+
+    /// Heads for the next cell on the path, jumping if it's a jump away.
+    fn walk(&mut self, grid: &Grid, others: &[Rect]) {
+        let rect = self.body.rect();
+        let feet = Self::feet(rect);
+        if let Some(i) = self.path.iter().position(|&cell| cell == feet) {
+            self.last = feet;
+            self.path.drain(..=i);
+        }
+        let Some(&(x, y)) = self.path.first() else { return; };
+
+        // a step up is just walking, anything higher or further is a jump
+        let across = x.abs_diff(self.last.0);
+        let jump = y < self.last.1 - 1 || across > 1;
+        let dx = (x as f32 + 0.5) * TILE_WIDTH as f32 - self.body.centre().0;
+        let ahead = Rect::new(rect.x + dx.signum() as i32, rect.y, rect.width(), rect.height());
+        let blocked = !grid.get_cols_in_rect(ahead).unwrap_or_default().is_empty();
+        // running into a wall it's about to jump up would only drag on it
+        if dx.abs() > 1.0 && !(jump && blocked) {
+            self.body.vel.0 += dx.signum() * CREATURE_RUN.min(dx.abs());
+        }
+        // from the far edge of the cell, to get as far as it can, or from right up against
+        // whatever's in the way
+        let at_edge = dx.abs() <= (across as f32 - 0.5) * TILE_WIDTH as f32;
+        if jump && (at_edge || blocked) && self.body.is_grounded(grid, others) {
+            self.body.vel.1 -= CREATURE_JUMP;
+        }
+    }
+}
+
+impl Entity for Creature {
+    fn body(&self) -> &Body {
+        &self.body
+    }
+
+    fn body_mut(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    fn colour(&self) -> Color {
+        match self.temper {
+            Temper::Timid => Color::RGB(190, 150, 90),
+            Temper::Hostile => Color::RGB(150, 30, 160),
+        }
+    }
+
+    fn think(&mut self, grid: &Grid, players: &[Rect]) {
+        let centre = self.body.centre();
+        let nearest = players.iter()
+            .map(|&player| (player, Vec2::from(player.center()).dist(&centre)))
+            .filter(|&(_, dist)| dist <= SIGHT)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(player, _)| player);
+
+        self.repath = self.repath.saturating_sub(1);
+        if nearest.is_some() && self.repath > 0 { return; }
+        let Some(to) = self.destination(nearest) else { return; };
+        // halfway through a jump there's nowhere to start from, the next tick will do
+        let feet = Self::feet(self.body.rect());
+        // a hostile one only goes after a player it can actually get to, running off or wandering
+        // anywhere close will do
+        let path = match (nearest, self.temper) {
+            (Some(_), Temper::Hostile) => grid.find_path(feet, to, CREATURE_STRIDE),
+            _ => grid.path_towards(feet, to, CREATURE_STRIDE),
+        };
+        if let Some(path) = path {
+            self.path = path;
+            self.last = feet;
+            self.repath = REPATH_TICKS;
+        }
+    }
+
+    fn update(&mut self, grid: &Grid, others: &[Rect]) {
+        self.walk(grid, others);
+        let grounded = self.body.is_grounded(grid, others);
+        self.lost = self.body.step(grid, others, if grounded { TILE_HEIGHT as i32 } else { 0 }).lost;
+    }
+
+    fn is_dead(&self) -> bool {
+        self.lost
+    }
+
+    fn bite(&self) -> f32 {
+        match self.temper {
+            Temper::Timid => 0.0,
+            Temper::Hostile => BITE_DAMAGE,
+        }
+    }
+}
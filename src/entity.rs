@@ -0,0 +1,326 @@
+//! Everything that moves about the world and isn't a tile: the players, and creatures,
+//! projectiles, items and whatever else comes along.
+//!
+//! They all have a `Body`, which does the falling, floating and bumping into things, and the main
+//! loop ticks and draws them together through `Entity`. Players are kept apart from the rest
+//! since there's so much more to them, `everyone` puts them back together when it doesn't matter.
+use super::*;
+
+const GRAVITY: f32 = 3.0;
+
+const PLAYER_DECELERATION: f32 = 0.85;
+
+const GROUND_FRICTION: f32 = 0.65;
+const HORIZ_AIR_DECELERATION: f32 = 0.4;
+const VERT_AIR_DECELERATION: f32 = 0.85;
+
+/// Cap on how much heavier than a body the stuff it's in counts as, lava would fire it out like a
+/// cannon otherwise.
+const MAX_BUOYANCY      : f32 = 1.5;
+
+/// Furthest a body gets drawn between ticks, anything more is a teleport.
+const MAX_TWEEN         : f32 = TILE_WIDTH as f32 * 4.0;
+
+/// What a body's in, averaged over its rect. Solid tiles count as nothing.
+#[derive(Default, Debug)]
+pub struct Medium {
+    pub density: f32,
+    pub viscosity: f32,
+    pub damage: f32,
+}
+
+/// How a tick of moving went.
+#[derive(Debug)]
+pub struct Moved {
+    /// What the body was in before it moved.
+    pub medium: Medium,
+    /// How fast it was falling when something stopped it, if something did.
+    pub landed: Option<f32>,
+    /// Fell out of the world into the void.
+    pub lost: bool,
+}
+
+/// Where something is, how big and how it's moving.
+#[derive(Default, Debug, Clone)]
+pub struct Body {
+    pub pos: Vec2,
+    /// Where it was before the last step, to draw it in between.
+    pub prev: Vec2,
+    pub vel: Vec2,
+    pub acc: Vec2,
+    pub w: u32,
+    pub h: u32,
+    /// Weighed against the density of what it's in to see if it floats, like `TileId::density`.
+    pub density: f32,
+}
+
+impl Body {
+    pub fn new(pos: Vec2, w: u32, h: u32, density: f32) -> Self {
+        Self { pos, prev: pos, w, h, density, ..Default::default() }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.0.ceil() as i32, self.pos.1.ceil() as i32, self.w, self.h)
+    }
+
+    pub fn centre(&self) -> Vec2 {
+        self.rect().center().into()
+    }
+
+    pub fn push(&mut self, v: Vec2) {
+        self.vel = self.vel + v;
+    }
+
+    /// Solid tiles overlapping `rect` and the rects in `others` that do, which are other bodies
+    /// that can be bumped into and stood on.
+    fn colliders(grid: &Grid, rect: Rect, others: &[Rect]) -> Vec<Rect> {
+        let mut cols = grid.get_cols_in_rect(rect).unwrap_or_default();
+        cols.extend(others.iter().filter(|other| other.has_intersection(rect)));
+        cols
+    }
+
+    pub fn is_grounded(&self, grid: &Grid, others: &[Rect]) -> bool {
+        let col_rect = Rect::new(self.pos.0 as i32 + self.w as i32/8, self.pos.1 as i32 + self.h as i32, self.w - self.w/8, self.h / 3);
+        !Self::colliders(grid, col_rect, others).is_empty()
+    }
+
+    pub fn medium(&self, grid: &Grid) -> Medium {
+        let area = (self.w * self.h) as f32;
+        let mut medium = Medium::default();
+        for (index, overlap) in grid.tiles_in_rect(self.rect()) {
+            let id = &TILES[index];
+            let part = overlap as f32 / area;
+            if id.solid { continue; }
+            medium.density += id.density * part;
+            medium.viscosity += id.viscosity * part;
+            medium.damage += id.damage * part;
+        }
+        medium
+    }
+
+    /// Lifts the body on top of `ledge` if it's no taller than `max_step` and there's room up
+    /// there.
+    fn step_up(&mut self, grid: &Grid, others: &[Rect], ledge: Rect, max_step: i32) -> bool {
+        let rect = self.rect();
+        let step = rect.bottom() - ledge.top();
+        // gravity might have sunk the body into the floor a bit, it gets pushed out after
+        let sunk = self.vel.1.max(0.0).ceil() as i32;
+        if max_step == 0 || step <= 0 || step > max_step + sunk { return false; }
+
+        let raised = Rect::new(rect.x, ledge.top() - self.h as i32, self.w, self.h);
+        if Self::colliders(grid, raised, others).is_empty() {
+            self.pos.1 = raised.y as f32;
+            true
+        }
+        else { false }
+    }
+
+    /// A tick of falling or floating, friction and drag, then moving as far as the velocity
+    /// takes it without going through the grid or the bodies at `others`. Walks up onto ledges
+    /// up to `max_step` pixels high instead of stopping at them.
+    pub fn step(&mut self, grid: &Grid, others: &[Rect], max_step: i32) -> Moved {
+        let prev = self.pos;
+        self.prev = prev;
+        let medium = self.medium(grid);
+        let lift = (medium.density / self.density).min(MAX_BUOYANCY);
+        self.acc.1 = GRAVITY * (1.0 - lift);
+        self.vel = self.vel + self.acc;
+        self.vel.1 = self.vel.1 * VERT_AIR_DECELERATION;
+        if self.is_grounded(grid, others) {
+            self.vel.0 *= GROUND_FRICTION;
+        }
+        else {
+            self.vel.0 *= HORIZ_AIR_DECELERATION;
+        }
+        self.acc.0 *= PLAYER_DECELERATION;
+        self.vel = self.vel * (1.0 - medium.viscosity.min(1.0));
+
+        self.pos = self.pos + self.vel;
+        // linef32 stops short of the end, which would swallow anything slower than a couple of
+        // pixels a tick, like floating up through water
+        let end = self.pos;
+        let falling = self.vel.1;
+        'substep: for (i, pt) in Vec2::linef32(prev, end).into_iter().chain([end]).enumerate() {
+            self.pos = pt;
+            let cols = Self::colliders(grid, self.rect(), others);
+            let len = cols.len();
+            for col in cols {
+                let body_rect = self.rect();
+                let col_obj_rect = col;
+
+                let Some(intersection) = body_rect.intersection(col_obj_rect) else { continue; };
+
+                // walking into a small ledge, hop up onto it rather than stopping dead
+                if intersection.w < intersection.h && self.step_up(grid, others, col_obj_rect, max_step) { continue; }
+
+                let pcentre: Vec2 = body_rect.center().into();
+                let ccentre = col_obj_rect.center().into();
+
+                let p_to_obj = pcentre - ccentre;
+                // eprintln!("{:?}", (ccentre - pcentre));
+
+                let (x_part, y_part) = if intersection.w == intersection.h {
+                    let p_to_obj = prev - self.pos;
+
+                    (p_to_obj.0.signum() * intersection.w as f32, p_to_obj.1.signum() * intersection.h as f32)
+                } else {
+                    let x_part = if intersection.w < intersection.h { p_to_obj.0.signum() * intersection.w as f32 } else { 0.0 };
+                    let y_part = if intersection.h < intersection.w { p_to_obj.1.signum() * intersection.h as f32 } else { 0.0 };
+                    (x_part, y_part)
+                };
+
+                self.pos = self.pos + Vec2(x_part, y_part);
+
+                if intersection.w < intersection.h { self.vel.0 = 0.0; }
+                if intersection.h < intersection.w { self.vel.1 = 0.0; }
+            }
+            if len > 0 && i > 1 {
+                break 'substep;
+            }
+        }
+
+        self.pos = grid.wrap_point(self.pos);
+        Moved {
+            medium,
+            landed: if self.vel.1 == 0.0 { Some(falling) } else { None },
+            lost: grid.lost_in_void(self.rect()),
+        }
+    }
+
+    /// Draws the body `alpha` of the way from where it was to where it is now.
+    pub fn draw(&self, canvas: &mut Canvas2, alpha: f32, colour: Color) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // don't smear it across the screen when it wraps around or respawns
+        let pos = if self.prev.dist(&self.pos) > MAX_TWEEN { self.pos } else { Vec2::lerp(&self.prev, &self.pos, alpha) };
+        canvas.set_draw_color(colour);
+        canvas.fill_rect(Rect::new(pos.0 as i32, pos.1 as i32, self.w, self.h))?;
+        Ok(())
+    }
+}
+
+pub trait Entity {
+    fn body(&self) -> &Body;
+
+    fn body_mut(&mut self) -> &mut Body;
+
+    fn colour(&self) -> Color;
+
+    /// Makes up its mind what to do, knowing where the players are. Called before every tick,
+    /// entities that don't think for themselves needn't bother.
+    fn think(&mut self, _grid: &Grid, _players: &[Rect]) {}
+
+    /// A tick of whatever it does, bumping into the grid and the entities at `others`. Just
+    /// physics unless it has something better to do.
+    fn update(&mut self, grid: &Grid, others: &[Rect]) {
+        self.body_mut().step(grid, others, 0);
+    }
+
+    fn draw(&self, canvas: &mut Canvas2, alpha: f32) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        self.body().draw(canvas, alpha, self.colour())
+    }
+
+    /// Shoved by tiles it's in the way of, see `Grid::take_pushes`.
+    fn shove(&mut self, push: Vec2) {
+        self.body_mut().push(push);
+    }
+
+    /// Does whatever it does to the grid, after everyone's moved.
+    fn affect(&mut self, _grid: &mut Grid) {}
+
+    /// Whether other entities bump into it and tiles pile up on it, rather than it going
+    /// straight through.
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    /// Health a player loses per tick touching it.
+    fn bite(&self) -> f32 {
+        0.0
+    }
+
+    /// Whether it's finished with and can go, checked after every tick.
+    fn is_dead(&self) -> bool {
+        false
+    }
+}
+
+/// Something that's only drawn, standing in for an entity that lives somewhere else, like on
+/// the server in a networked game.
+pub struct Puppet {
+    pub body: Body,
+    pub colour: Color,
+}
+
+impl Entity for Puppet {
+    fn body(&self) -> &Body {
+        &self.body
+    }
+
+    fn body_mut(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    fn colour(&self) -> Color {
+        self.colour
+    }
+
+    fn update(&mut self, _grid: &Grid, _others: &[Rect]) {}
+}
+
+/// The players and then the other entities, to do the same thing to all of them.
+pub fn everyone<'a>(players: &'a mut [Player], entities: &'a mut [Box<dyn Entity>]) -> Vec<&'a mut (dyn Entity + 'static)> {
+    players.iter_mut()
+        .map(|p| p as &mut dyn Entity)
+        .chain(entities.iter_mut().map(|e| e.as_mut()))
+        .collect()
+}
+
+/// A tick for every entity in turn, each bumping into wherever the others are by then so two
+/// running at each other don't both end up in the same gap.
+fn update_all(entities: &mut [&mut (dyn Entity + 'static)], grid: &Grid) {
+    for i in 0..entities.len() {
+        let others: Vec<Rect> = entities.iter().enumerate().filter(|&(j, e)| j != i && e.is_solid()).map(|(_, e)| e.body().rect()).collect();
+        entities[i].update(grid, &others);
+    }
+}
+
+/// Where everything solid is, for `Grid::set_obstacles`. The pushes come back in the same order,
+/// the players and then the solid entities, see `shove`.
+pub fn obstacles(players: &[Player], entities: &[Box<dyn Entity>]) -> Vec<Rect> {
+    players.iter().map(|p| p.body.rect())
+        .chain(entities.iter().filter(|e| e.is_solid()).map(|e| e.body().rect()))
+        .collect()
+}
+
+/// Hands the pushes from `Grid::take_pushes` out to whoever they were for. They only line up with
+/// the `obstacles` they came from, so this has to be straight after the update, before anything
+/// turns up or goes.
+pub fn shove(players: &mut [Player], entities: &mut [Box<dyn Entity>], pushes: Vec<Vec2>) {
+    for (entity, push) in everyone(players, entities).into_iter().filter(|e| e.is_solid()).zip(pushes) {
+        entity.shove(push);
+    }
+}
+
+/// A tick for the players and everything else: the entities make up their minds, everyone moves
+/// and does whatever it does to the grid, then anything that bites bites whoever it's touching.
+/// Entities that are finished with go.
+pub fn tick(players: &mut [Player], entities: &mut Vec<Box<dyn Entity>>, grid: &mut Grid) {
+    let rects: Vec<Rect> = players.iter().map(|p| p.body.rect()).collect();
+    for entity in entities.iter_mut() {
+        entity.think(grid, &rects);
+    }
+    update_all(&mut everyone(players, entities), grid);
+    for entity in entities.iter_mut() {
+        entity.affect(grid);
+    }
+
+    for player in players.iter_mut() {
+        // nothing overlaps the player, so touching them is being right up against them
+        let rect = player.body.rect();
+        let reach = Rect::new(rect.x - 1, rect.y - 1, rect.w as u32 + 2, rect.h as u32 + 2);
+        let bites: f32 = entities.iter().filter(|e| e.body().rect().has_intersection(reach)).map(|e| e.bite()).sum();
+        if bites > 0.0 {
+            player.hurt(bites);
+        }
+    }
+    entities.retain(|e| !e.is_dead());
+}
@@ -0,0 +1,69 @@
+//! Game controllers, plugged in and out whenever. Their buttons go through `Bindings` like keys
+//! do, the sticks are read here.
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::vec2::Vec2;
+
+/// How far a stick has to be pushed before it counts, sticks never quite centre.
+const STICK_DEADZONE    : f32 = 0.2;
+
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    pads: Vec<GameController>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        Self { subsystem, pads: vec![] }
+    }
+
+    /// Opens and closes controllers as they come and go. SDL sends an added event for every
+    /// controller that's already plugged in at the start, so that's all there is to it.
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(pad) => self.pads.push(pad),
+                Err(e) => eprintln!("couldn't open controller {}: {}", which, e),
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.pads.retain(|pad| pad.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    /// Which controller it is counting in the order they were plugged in, from the instance id
+    /// SDL puts in its events.
+    pub fn slot(&self, instance_id: u32) -> Option<usize> {
+        self.pads.iter().position(|pad| pad.instance_id() == instance_id)
+    }
+
+    /// Whether `button` is held down on the controller in `slot`.
+    pub fn button(&self, slot: usize, button: Button) -> bool {
+        self.pads.get(slot).is_some_and(|pad| pad.button(button))
+    }
+
+    /// Where a stick is pushed on `pad`, from -1.0 to 1.0 on each axis, nowhere if there's no
+    /// such controller.
+    fn stick(pad: Option<&GameController>, x: Axis, y: Axis) -> Vec2 {
+        let Some(pad) = pad else { return Vec2::ZERO; };
+        let axis = |axis| {
+            let v = pad.axis(axis) as f32 / i16::MAX as f32;
+            // start from nothing at the edge of the deadzone, not from a jump
+            if v.abs() < STICK_DEADZONE { 0.0 } else { (v - STICK_DEADZONE * v.signum()) / (1.0 - STICK_DEADZONE) }
+        };
+        Vec2(axis(x).clamp(-1.0, 1.0), axis(y).clamp(-1.0, 1.0))
+    }
+
+    /// The left stick of the controller in `slot`, for running.
+    pub fn movement(&self, slot: usize) -> Vec2 {
+        Self::stick(self.pads.get(slot), Axis::LeftX, Axis::LeftY)
+    }
+
+    /// The right stick of the controller in `slot`, for moving its player's cursor.
+    pub fn aim(&self, slot: usize) -> Vec2 {
+        Self::stick(self.pads.get(slot), Axis::RightX, Axis::RightY)
+    }
+}
@@ -0,0 +1,136 @@
+//! Classic cellular automata, run on the same grid (and drawn the same way) as the sandbox, in
+//! place of the material rules. Unlike the sandbox every cell updates at once, from a copy of the
+//! last tick. Anything that isn't one of the automaton's own materials is left alone and counts
+//! as dead, so you can build walls around things with stone.
+use super::neighbour::{Neighbour, NOSLIP_NEIGHBOURS};
+use super::{Grid, Tile, TileIndex};
+use crate::{AIR, ANT, CELL, COPPER, DYING, HEAD, TAIL};
+
+/// Set in an ant's `Tile::state` when the cell it's standing on is black, the bottom two bits
+/// are its heading.
+const ANT_ON_CELL: u8 = 0b100;
+/// Headings of an ant, in clockwise order.
+const ANT_HEADINGS: [Neighbour; 4] = [Neighbour::Up, Neighbour::Right, Neighbour::Down, Neighbour::Left];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    /// The normal material rules.
+    #[default]
+    Sandbox,
+    /// Conway's Game of Life on `Cell`s.
+    Life,
+    /// `Cell`s fire, become `Dying` and then go out, air with two firing neighbours fires.
+    BriansBrain,
+    /// Electron `Head`s run along `Copper`, leaving a `Tail` behind them.
+    Wireworld,
+    /// Every `Ant` turns right on air and left on `Cell`s, flipping the one it leaves.
+    LangtonsAnt,
+}
+
+impl Mode {
+    pub const ALL: &'static [Mode] = &[Mode::Sandbox, Mode::Life, Mode::BriansBrain, Mode::Wireworld, Mode::LangtonsAnt];
+
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> Mode {
+        let i = Mode::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Mode::ALL[(i + 1) % Mode::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Sandbox       => "Sandbox",
+            Mode::Life          => "Life",
+            Mode::BriansBrain   => "Brian's Brain",
+            Mode::Wireworld     => "Wireworld",
+            Mode::LangtonsAnt   => "Langton's Ant",
+        }
+    }
+}
+
+impl Grid {
+    /// How many of the 8 cells around `(x, y)` are `index`.
+    fn count_around(&self, x: usize, y: usize, index: TileIndex) -> usize {
+        NOSLIP_NEIGHBOURS.iter()
+            .filter(|n| **n != Neighbour::Ident)
+            .filter_map(|n| {
+                let (dx, dy) = n.offset();
+                self.resolve(x as isize + dx, y as isize + dy)
+            })
+            .filter(|p| self[*p].index == index)
+            .count()
+    }
+
+    pub(super) fn step_automaton(&mut self) {
+        if self.mode == Mode::LangtonsAnt {
+            self.step_ants();
+            return;
+        }
+
+        let (w, h) = self.get_wh();
+        let next = (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| self.next_cell(x, y))
+            .collect();
+        self.grid = next;
+    }
+
+    /// What `(x, y)` becomes next tick, for the modes where every cell updates at once.
+    fn next_cell(&self, x: usize, y: usize) -> Tile {
+        let tile = self[(x, y)];
+        let next = match (self.mode, tile.index) {
+            (Mode::Life, CELL) => {
+                if (2..=3).contains(&self.count_around(x, y, CELL)) { CELL } else { AIR }
+            }
+            (Mode::Life, AIR) => {
+                if self.count_around(x, y, CELL) == 3 { CELL } else { AIR }
+            }
+
+            (Mode::BriansBrain, CELL) => DYING,
+            (Mode::BriansBrain, DYING) => AIR,
+            (Mode::BriansBrain, AIR) => {
+                if self.count_around(x, y, CELL) == 2 { CELL } else { AIR }
+            }
+
+            (Mode::Wireworld, HEAD) => TAIL,
+            (Mode::Wireworld, TAIL) => COPPER,
+            (Mode::Wireworld, COPPER) => {
+                if (1..=2).contains(&self.count_around(x, y, HEAD)) { HEAD } else { COPPER }
+            }
+
+            (_, index) => index,
+        };
+        if next == tile.index { tile } else { Tile::new(next) }
+    }
+
+    /// Ants go one at a time, an ant can't walk into another one or anything but air and cells,
+    /// it turns around instead.
+    fn step_ants(&mut self) {
+        let (w, h) = self.get_wh();
+        let ants: Vec<_> = (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .filter(|p| self[*p].index == ANT)
+            .collect();
+
+        for (x, y) in ants {
+            let state = self[(x, y)].state;
+            let on_cell = state & ANT_ON_CELL != 0;
+            let heading = state & 0b11;
+            let heading = if on_cell { (heading + 3) % 4 } else { (heading + 1) % 4 };
+
+            let (dx, dy) = ANT_HEADINGS[heading as usize].offset();
+            let ahead = self.resolve(x as isize + dx, y as isize + dy)
+                .filter(|p| self[*p].index == AIR || self[*p].index == CELL);
+            match ahead {
+                Some(p) => {
+                    let mut ant = Tile::new(ANT);
+                    ant.state = heading | if self[p].index == CELL { ANT_ON_CELL } else { 0 };
+                    self[(x, y)] = Tile::new(if on_cell { AIR } else { CELL });
+                    self[p] = ant;
+                }
+                None => {
+                    self[(x, y)].state = ((heading + 2) % 4) | (state & ANT_ON_CELL);
+                }
+            }
+        }
+    }
+}
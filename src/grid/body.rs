@@ -0,0 +1,321 @@
+//! Rigid bodies, groups of tiles that move and tumble together.
+//!
+//! A body is lifted out of the grid when it's made, and every tick it's erased from the grid,
+//! moved, and drawn back in (rasterised) wherever it ended up, so everything else in the grid
+//! just sees ordinary tiles. A body that's been marked as one stays one until it's unmarked, so a
+//! beam that's resting on something falls as soon as that's cut away. Ones that came loose by
+//! themselves, like collapsing stone, are left where they are once they've been lying still for a
+//! while and go back to being part of the grid. Anything that falls out through the void is
+//! gone, marked or not.
+use std::collections::HashMap;
+
+use super::explosion::Particle;
+use super::{Edge, Grid, Tile, TILES};
+use crate::vec2::Vec2;
+use crate::AIR;
+
+/// Biggest group of tiles that can be turned into a body, flood fills on a whole level get slow.
+const MAX_BODY_TILES    : usize = 600;
+/// In tiles per tick per tick.
+const BODY_GRAVITY      : f32 = 0.15;
+const BODY_MAX_FALL     : f32 = 2.0;
+const BODY_FRICTION     : f32 = 0.7;
+/// How quickly a body starts to tip over when it's hanging off the edge of something.
+const TIP_TORQUE        : f32 = 0.01;
+const MAX_SPIN          : f32 = 0.12;
+/// Ticks a body that isn't marked has to sit still before it goes back into the grid.
+const SETTLE_TICKS      : u32 = 30;
+
+pub struct RigidBody {
+    /// Every tile of the body, by its cell in the body's own frame.
+    tiles: HashMap<(isize, isize), Tile>,
+    /// Centre of mass, in the body's own frame.
+    com: Vec2,
+    /// Where the centre of mass is in the grid, in tiles.
+    pos: Vec2,
+    vel: Vec2,
+    angle: f32,
+    spin: f32,
+    /// Furthest any tile is from the centre of mass, so we know how far around to look.
+    reach: f32,
+    /// Cells the body was drawn into last tick, to erase it again.
+    pub(super) stamped: Vec<(usize, usize)>,
+    still_for: u32,
+    /// Made a body on purpose, it doesn't settle back into the grid.
+    marked: bool,
+}
+
+impl RigidBody {
+    /// Which cells of the grid the body covers when it's at `pos` turned by `angle`, and the
+    /// tiles that go in them. Works backwards from the grid cells to the body's cells so there
+    /// are no holes when it's at an angle.
+    fn cells(&self, pos: Vec2, angle: f32) -> Vec<((isize, isize), Tile)> {
+        let r = self.reach.ceil() as isize + 1;
+        let (cx, cy) = (pos.0.round() as isize, pos.1.round() as isize);
+        let mut res = vec![];
+        for y in cy - r..=cy + r {
+            for x in cx - r..=cx + r {
+                let local = (Vec2(x as f32, y as f32) - pos).rotate(-angle) + self.com;
+                // halves always round the same way, or a body exactly between two cells has none
+                let key = ((local.0 + 0.5).floor() as isize, (local.1 + 0.5).floor() as isize);
+                if let Some(tile) = self.tiles.get(&key) {
+                    res.push(((x, y), *tile));
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Grid {
+    /// Lifts the tiles of the same material connected to `(x, y)` out of the grid and makes a
+    /// marked rigid body of them. Only works on solid stuff that doesn't move by itself. If
+    /// there's a body there already it's marked or unmarked instead.
+    pub fn make_body(&mut self, x: usize, y: usize) -> bool {
+        if let Some(body) = self.bodies.iter_mut().find(|b| b.stamped.contains(&(x, y))) {
+            body.marked = !body.marked;
+            return true;
+        }
+        let Some(cells) = self.connected(x, y, MAX_BODY_TILES) else { return false; };
+        self.lift_body(cells, true);
+        true
+    }
+
+    /// Cells of the same solid, still material connected to `(x, y)`, `None` if there's nothing
+    /// like that there or there's more than `max` of them.
+    pub(super) fn connected(&self, x: usize, y: usize, max: usize) -> Option<Vec<(usize, usize)>> {
+        let index = self.index_at(x, y)?;
+        let id = &TILES[index];
+        if !id.solid || !id.rules.is_empty() || id.emits.is_some() || id.sink { return None; }
+
+        let mut seen = vec![(x, y)];
+        let mut todo = vec![(x, y)];
+        while let Some((x, y)) = todo.pop() {
+            for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+                let Some(p) = self.resolve(x as isize + dx, y as isize + dy) else { continue; };
+                if self[p].index == index && !seen.contains(&p) {
+                    if seen.len() >= max { return None; }
+                    seen.push(p);
+                    todo.push(p);
+                }
+            }
+        }
+        Some(seen)
+    }
+
+    /// Turns `cells` into a body, taking them out of the grid.
+    pub(super) fn lift_body(&mut self, cells: Vec<(usize, usize)>, marked: bool) {
+        let (ox, oy) = cells[0];
+        let mut tiles = HashMap::new();
+        let mut sum = Vec2::ZERO;
+        for &(x, y) in &cells {
+            // unwrap across wrapping edges, so the body doesn't come apart
+            let (w, h) = self.get_wh();
+            let dx = (x as isize - ox as isize + w as isize / 2).rem_euclid(w as isize) - w as isize / 2;
+            let dy = (y as isize - oy as isize + h as isize / 2).rem_euclid(h as isize) - h as isize / 2;
+            tiles.insert((dx, dy), self[(x, y)]);
+            sum = sum + Vec2(dx as f32, dy as f32);
+            self[(x, y)] = Tile::default();
+        }
+        let com = sum * (1.0 / cells.len() as f32);
+        let reach = tiles.keys()
+            .map(|(x, y)| Vec2(*x as f32, *y as f32).dist(&com))
+            .fold(0.0, f32::max);
+
+        let mut body = RigidBody {
+            tiles, com, reach,
+            pos: Vec2(ox as f32, oy as f32) + com,
+            vel: Vec2::ZERO,
+            angle: 0.0,
+            spin: 0.0,
+            stamped: vec![],
+            still_for: 0,
+            marked,
+        };
+        self.stamp_body(&mut body);
+        self.bodies.push(body);
+    }
+
+    /// Whether the body would hit anything at `pos` and `angle`, obstacles included.
+    fn body_fits(&self, body: &RigidBody, pos: Vec2, angle: f32) -> bool {
+        body.cells(pos, angle).into_iter().all(|((x, y), _)| match self.locate(x, y) {
+            Ok(p) => !TILES[self[p].index].solid && !self.obstacles.contains_key(&p),
+            Err(edge) => edge != Edge::Wall,
+        })
+    }
+
+    /// The first obstacle the body would be on top of at `pos` and `angle`, if any.
+    fn body_blocker(&self, body: &RigidBody, pos: Vec2, angle: f32) -> Option<usize> {
+        body.cells(pos, angle).into_iter()
+            .find_map(|((x, y), _)| self.obstacles.get(&self.locate(x, y).ok()?).copied())
+    }
+
+    fn stamp_body(&mut self, body: &mut RigidBody) {
+        body.stamped.clear();
+        for ((x, y), tile) in body.cells(body.pos, body.angle) {
+            let Some(p) = self.resolve(x, y) else { continue; };
+            // liquids and gases in the way get splashed up out of it
+            if self[p].index != AIR {
+                self.particles.push(Particle {
+                    pos: Vec2(p.0 as f32, p.1 as f32),
+                    vel: Vec2(self.rng.unit() - 0.5, -1.0),
+                    tile: self[p],
+                });
+            }
+            self[p] = tile;
+            self[p].updated = true;
+            body.stamped.push(p);
+        }
+    }
+
+    fn erase_body(&mut self, body: &RigidBody) {
+        for &p in &body.stamped {
+            // something else might have taken the cell, like an explosion
+            if body.tiles.values().any(|t| t.index == self[p].index) {
+                self[p] = Tile::default();
+            }
+        }
+    }
+
+    pub(super) fn update_bodies(&mut self) {
+        let bodies = std::mem::take(&mut self.bodies);
+        for mut body in bodies {
+            self.erase_body(&body);
+
+            body.vel.1 = (body.vel.1 + BODY_GRAVITY).min(BODY_MAX_FALL);
+            let steps = body.vel.dist(&Vec2::ZERO).ceil().max(1.0) as usize;
+            let step = body.vel * (1.0 / steps as f32);
+            let mut landed = false;
+            for _ in 0..steps {
+                if self.body_fits(&body, body.pos + step, body.angle) {
+                    body.pos = body.pos + step;
+                    continue;
+                }
+                if self.body_fits(&body, body.pos + Vec2(step.0, 0.0), body.angle) {
+                    body.pos.0 += step.0;
+                } else {
+                    body.vel.0 = 0.0;
+                }
+                if self.body_fits(&body, body.pos + Vec2(0.0, step.1), body.angle) {
+                    body.pos.1 += step.1;
+                } else {
+                    // coming down on the player, say, weighs on them
+                    if let Some(i) = self.body_blocker(&body, body.pos + Vec2(0.0, step.1), body.angle).filter(|_| step.1 > 0.0) {
+                        self.push_obstacle(i, (0, 1));
+                    }
+                    landed |= body.vel.1 > 0.0;
+                    body.vel.1 = 0.0;
+                }
+            }
+
+            if landed {
+                body.vel.0 *= BODY_FRICTION;
+                self.tip_body(&mut body);
+            }
+            if body.spin != 0.0 {
+                let angle = body.angle + body.spin;
+                let dir = body.spin.signum();
+                // turning about the centre of mass digs into whatever it's tipping over on, so
+                // let it shuffle a bit to make room
+                let nudge = [Vec2::ZERO, Vec2(0.0, -1.0), Vec2(dir, 0.0), Vec2(dir, -1.0)]
+                    .into_iter()
+                    .find(|n| self.body_fits(&body, body.pos + *n, angle));
+                match nudge {
+                    Some(n) => { body.angle = angle; body.pos = body.pos + n; }
+                    None => body.spin = 0.0,
+                }
+            }
+
+            self.stamp_body(&mut body);
+            // gone off the grid through the void, it's never coming back
+            if body.stamped.is_empty() { continue; }
+
+            let still = landed && body.vel.0.abs() < 0.05 && body.spin == 0.0;
+            body.still_for = if still { body.still_for + 1 } else { 0 };
+            if body.marked || body.still_for < SETTLE_TICKS {
+                self.bodies.push(body);
+            }
+        }
+    }
+
+    /// Starts a body tipping over if its centre of mass isn't above what it's resting on.
+    fn tip_body(&self, body: &mut RigidBody) {
+        let supports: Vec<f32> = body.cells(body.pos, body.angle).into_iter()
+            .filter(|((x, y), _)| match self.locate(*x, y + 1) {
+                Ok(p) => TILES[self[p].index].solid && !body.stamped.contains(&p),
+                Err(edge) => edge == Edge::Wall,
+            })
+            .map(|((x, _), _)| x as f32)
+            .collect();
+        let Some(left) = supports.iter().cloned().reduce(f32::min) else { return; };
+        let right = supports.iter().cloned().fold(left, f32::max);
+
+        if body.pos.0 < left - 0.5 {
+            body.spin = (body.spin - TIP_TORQUE).max(-MAX_SPIN);
+        } else if body.pos.0 > right + 0.5 {
+            body.spin = (body.spin + TIP_TORQUE).min(MAX_SPIN);
+        } else {
+            body.spin = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundary;
+    use crate::STONE;
+
+    const SIZE: usize = 12;
+
+    /// A grid with a three tile beam of stone floating near the top of it.
+    fn beam(boundary: Boundary) -> Grid {
+        let mut grid = Grid::new(SIZE, SIZE).unwrap();
+        grid.set_boundary(boundary);
+        for x in 4..7 {
+            grid[(x, 2)] = Tile::new(STONE);
+        }
+        grid
+    }
+
+    fn stone(grid: &Grid) -> Vec<(usize, usize)> {
+        (0..SIZE).flat_map(|y| (0..SIZE).map(move |x| (x, y))).filter(|&p| grid[p].index == STONE).collect()
+    }
+
+    fn run(grid: &mut Grid, ticks: usize) {
+        for _ in 0..ticks {
+            grid.update().unwrap();
+        }
+    }
+
+    #[test]
+    fn falls_lands_and_settles() {
+        let mut grid = beam(Boundary::all(Edge::Wall));
+        let cells = grid.connected(5, 2, MAX_BODY_TILES).unwrap();
+        grid.lift_body(cells, false);
+        run(&mut grid, 5);
+        assert!(stone(&grid).iter().all(|&(_, y)| y > 2), "hasn't fallen: {:?}", stone(&grid));
+        run(&mut grid, 30 + SETTLE_TICKS as usize);
+        assert!(grid.bodies.is_empty());
+        assert_eq!(stone(&grid), vec![(4, SIZE - 1), (5, SIZE - 1), (6, SIZE - 1)]);
+    }
+
+    #[test]
+    fn marked_stays_a_body() {
+        let mut grid = beam(Boundary::all(Edge::Wall));
+        assert!(grid.make_body(5, 2));
+        run(&mut grid, 30 + SETTLE_TICKS as usize);
+        assert_eq!(grid.bodies.len(), 1);
+        assert_eq!(stone(&grid).len(), 3);
+        assert!(stone(&grid).iter().all(|&(_, y)| y == SIZE - 1));
+    }
+
+    #[test]
+    fn falls_through_the_void() {
+        let mut grid = beam(Boundary::default());
+        assert!(grid.make_body(5, 2));
+        run(&mut grid, 60);
+        assert!(grid.bodies.is_empty());
+        assert!(stone(&grid).is_empty());
+    }
+}
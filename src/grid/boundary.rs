@@ -0,0 +1,105 @@
+//! What the edges of the grid do to things that run into them.
+use super::{Grid, Sample, TILE_HEIGHT, TILE_WIDTH};
+use crate::vec2::Vec2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Edge {
+    /// The edge is solid, nothing gets through it.
+    Wall,
+    /// The edge is empty, anything that goes through it is deleted (or lost, for the player).
+    /// Grains have always fallen off the grid, so this is what you get to start with.
+    #[default]
+    Void,
+    /// Going through the edge brings you back in on the opposite side.
+    Wrap,
+}
+
+/// The `Edge` on each side of the grid.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Boundary {
+    pub left    : Edge,
+    pub right   : Edge,
+    pub top     : Edge,
+    pub bottom  : Edge,
+}
+
+impl Boundary {
+    pub const fn all(edge: Edge) -> Self {
+        Self { left: edge, right: edge, top: edge, bottom: edge }
+    }
+}
+
+/// Some handy boundaries to cycle through, with a name to show for them. The first is the
+/// default one.
+pub const PRESETS: &[(&str, Boundary)] = &[
+    ("Void", Boundary::all(Edge::Void)),
+    ("Walls", Boundary::all(Edge::Wall)),
+    ("Wrap", Boundary::all(Edge::Wrap)),
+    ("Bottomless", Boundary { bottom: Edge::Void, ..Boundary::all(Edge::Wall) }),
+    ("Cylinder", Boundary { left: Edge::Wrap, right: Edge::Wrap, ..Boundary::all(Edge::Wall) }),
+];
+
+/// Brings `v` back into `0..len` if it went through an edge that wraps, otherwise says which
+/// edge it hit.
+fn fold(v: isize, len: isize, low: Edge, high: Edge) -> Result<isize, Edge> {
+    let edge = if v < 0 { low } else if v >= len { high } else { return Ok(v) };
+    match edge {
+        Edge::Wrap => Ok(v.rem_euclid(len)),
+        edge => Err(edge),
+    }
+}
+
+impl Grid {
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = boundary;
+    }
+
+    /// Where the cell `(x, y)` really is once wrapping is taken into account, or the edge that's
+    /// in the way. Off a corner a wall wins over the void.
+    pub(super) fn locate(&self, x: isize, y: isize) -> Result<(usize, usize), Edge> {
+        let (w, h) = self.get_wh();
+        let b = self.boundary;
+        match (fold(x, w as isize, b.left, b.right), fold(y, h as isize, b.top, b.bottom)) {
+            (Ok(x), Ok(y)) => Ok((x as usize, y as usize)),
+            (Err(Edge::Wall), _) | (_, Err(Edge::Wall)) => Err(Edge::Wall),
+            (Err(edge), _) | (_, Err(edge)) => Err(edge),
+        }
+    }
+
+    /// Where `(x, y)` actually is in the grid, `None` if it's off the edge.
+    pub(super) fn resolve(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        self.locate(x, y).ok()
+    }
+
+    /// The tile at `(x, y)`, or whatever the edge is if it's off the grid.
+    pub(super) fn sample(&self, x: isize, y: isize) -> Sample {
+        match self.locate(x, y) {
+            Ok(p) => Sample::Tile(self[p]),
+            Err(Edge::Void) => Sample::Void,
+            Err(_) => Sample::Wall,
+        }
+    }
+
+    /// Brings a point in pixels back onto the grid across any edges that wrap.
+    pub fn wrap_point(&self, p: Vec2) -> Vec2 {
+        let (w, h) = self.get_wh();
+        let (w, h) = ((w * TILE_WIDTH) as f32, (h * TILE_HEIGHT) as f32);
+        let b = self.boundary;
+        let wrap = |v: f32, len: f32, low: Edge, high: Edge| {
+            if (v < 0.0 && low == Edge::Wrap) || (v >= len && high == Edge::Wrap) { v.rem_euclid(len) } else { v }
+        };
+        Vec2(wrap(p.0, w, b.left, b.right), wrap(p.1, h, b.top, b.bottom))
+    }
+
+    /// Whether a rect in pixels has gone completely off the grid through the void, and won't be
+    /// coming back.
+    pub fn lost_in_void(&self, rect: sdl2::rect::Rect) -> bool {
+        let (w, h) = self.get_wh();
+        let (w, h) = ((w * TILE_WIDTH) as i32, (h * TILE_HEIGHT) as i32);
+        let b = self.boundary;
+        (rect.right() <= 0 && b.left == Edge::Void)
+            || (rect.left() >= w && b.right == Edge::Void)
+            || (rect.bottom() <= 0 && b.top == Edge::Void)
+            || (rect.top() >= h && b.bottom == Edge::Void)
+    }
+}
@@ -0,0 +1,144 @@
+//! The grid cut up into square chunks, so it can be sent over the network a bit at a time.
+//!
+//! A chunk is sent as runs of the same tile going along the rows, a byte for how many, a byte for
+//! the material and a byte for its charge, which squashes the big stretches of air and stone most
+//! chunks are down to a few bytes. That's all there is to how a tile looks, so how old tiles are
+//! and the automaton state in them aren't kept, a client can't carry on simulating from a chunk.
+use super::{Grid, GridResult, Result, Tile, TILES};
+
+/// Width and height of a chunk, in tiles.
+pub const CHUNK_SIZE    : usize = 8;
+
+/// Materials and charges as runs, a run that would be longer than a byte can count goes on in
+/// another one.
+fn encode_runs(tiles: impl Iterator<Item = (u8, u8)>) -> Vec<u8> {
+    let mut res: Vec<u8> = vec![];
+    for (index, charge) in tiles {
+        match res.len() {
+            n if n >= 3 && res[n - 2..] == [index, charge] && res[n - 3] < u8::MAX => res[n - 3] += 1,
+            _ => res.extend([1, index, charge]),
+        }
+    }
+    res
+}
+
+impl Grid {
+    /// How many chunks there are across and down, the last ones might hang over the edge.
+    pub fn chunks(&self) -> (usize, usize) {
+        let (w, h) = self.get_wh();
+        (w.div_ceil(CHUNK_SIZE), h.div_ceil(CHUNK_SIZE))
+    }
+
+    /// Cells of chunk `(cx, cy)` that are on the grid, row by row.
+    fn chunk_cells(&self, cx: usize, cy: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (w, h) = self.get_wh();
+        let (x0, y0) = (cx * CHUNK_SIZE, cy * CHUNK_SIZE);
+        (y0..(y0 + CHUNK_SIZE).min(h)).flat_map(move |y| (x0..(x0 + CHUNK_SIZE).min(w)).map(move |x| (x, y)))
+    }
+
+    /// Chunk `(cx, cy)` run length encoded.
+    pub fn encode_chunk(&self, cx: usize, cy: usize) -> Vec<u8> {
+        encode_runs(self.chunk_cells(cx, cy).map(|p| (self[p].index as u8, self[p].charge)))
+    }
+
+    /// Fills chunk `(cx, cy)` back in from what `encode_chunk` made of it.
+    pub fn decode_chunk(&mut self, cx: usize, cy: usize, data: &[u8]) -> Result<()> {
+        let (w, h) = self.chunks();
+        if cx >= w || cy >= h {
+            return Err(GridResult::OOB);
+        }
+        let bad = |why: &str| GridResult::BadWorld(format!("chunk {},{} {}", cx, cy, why));
+        let mut runs = data.chunks(3).flat_map(|run| match *run {
+            [count, index, charge] => vec![Some((index as usize, charge)); count as usize],
+            _ => vec![None],
+        });
+        let cells: Vec<_> = self.chunk_cells(cx, cy).collect();
+        for p in cells {
+            let (index, charge) = runs.next().flatten().ok_or_else(|| bad("is cut short"))?;
+            if index >= TILES.len() {
+                return Err(bad("has an unknown material in it"));
+            }
+            if self[p].index != index {
+                self[p] = Tile::new(index);
+            }
+            self[p].charge = charge;
+        }
+        if runs.next().is_some() {
+            return Err(bad("runs on too long"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COPPER, SAND, STONE, WATER};
+
+    /// A grid whose last chunks across and down hang over the edge, with a bit of everything in.
+    fn grid() -> Grid {
+        let mut grid = Grid::new(CHUNK_SIZE + 3, CHUNK_SIZE + 5).unwrap();
+        let (w, h) = grid.get_wh();
+        for y in 0..h {
+            for x in 0..w {
+                grid[(x, y)] = Tile::new([STONE, SAND, WATER][(x * 7 + y * 3) % 4 % 3]);
+            }
+        }
+        grid[(w - 1, h - 1)] = Tile { charge: 2, ..Tile::new(COPPER) };
+        grid
+    }
+
+    fn same(a: &Grid, b: &Grid) -> bool {
+        a.grid.iter().zip(&b.grid).all(|(a, b)| a.index == b.index && a.charge == b.charge)
+    }
+
+    #[test]
+    fn round_trip() {
+        let from = grid();
+        let mut to = Grid::new(CHUNK_SIZE + 3, CHUNK_SIZE + 5).unwrap();
+        let (cw, ch) = from.chunks();
+        assert_eq!((cw, ch), (2, 2));
+        for (cx, cy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            to.decode_chunk(cx, cy, &from.encode_chunk(cx, cy)).unwrap();
+        }
+        assert!(same(&from, &to));
+    }
+
+    #[test]
+    fn edge_chunk() {
+        let from = grid();
+        // only the 3 by 5 tiles of the corner chunk that are on the grid
+        let data = from.encode_chunk(1, 1);
+        assert_eq!(data.chunks(3).map(|run| run[0] as usize).sum::<usize>(), 15);
+        let mut to = Grid::new(CHUNK_SIZE + 3, CHUNK_SIZE + 5).unwrap();
+        to.decode_chunk(1, 1, &data).unwrap();
+        assert_eq!((to[(CHUNK_SIZE + 2, CHUNK_SIZE + 4)].index, to[(CHUNK_SIZE + 2, CHUNK_SIZE + 4)].charge), (COPPER, 2));
+        assert!(to.decode_chunk(2, 1, &data).is_err());
+    }
+
+    #[test]
+    fn long_runs() {
+        let runs = encode_runs(std::iter::repeat_n((SAND as u8, 0), 300));
+        assert_eq!(runs, [255, SAND as u8, 0, 45, SAND as u8, 0]);
+        // a whole chunk of the same is a single run
+        let grid = Grid::new(CHUNK_SIZE, CHUNK_SIZE).unwrap();
+        assert_eq!(grid.encode_chunk(0, 0), [(CHUNK_SIZE * CHUNK_SIZE) as u8, 0, 0]);
+    }
+
+    #[test]
+    fn bad_data() {
+        let from = grid();
+        let data = from.encode_chunk(0, 0);
+        let mut to = Grid::new(CHUNK_SIZE + 3, CHUNK_SIZE + 5).unwrap();
+        // a whole run short, half a run short
+        assert!(to.decode_chunk(0, 0, &data[..data.len() - 3]).is_err());
+        assert!(to.decode_chunk(0, 0, &data[..data.len() - 1]).is_err());
+        // a run too many, half a run too many
+        assert!(to.decode_chunk(0, 0, &[&data[..], &[1, 0, 0]].concat()).is_err());
+        assert!(to.decode_chunk(0, 0, &[&data[..], &[1]].concat()).is_err());
+        // no such material
+        let mut unknown = data.clone();
+        unknown[1] = TILES.len() as u8;
+        assert!(to.decode_chunk(0, 0, &unknown).is_err());
+    }
+}
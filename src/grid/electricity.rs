@@ -0,0 +1,64 @@
+//! Sparks running through conductive tiles.
+//!
+//! A tile with a full `SPARK` charge is the front of a spark. Every tick it passes the spark on
+//! to the conductive tiles next to it that aren't charged at all, sets fire to flammable ones and
+//! runs its `TileId::powered` rules, then its charge drains away one tick at a time. Tiles that
+//! are still draining can't take a new spark, which is what stops sparks going backwards.
+use super::neighbour::Neighbour;
+use super::{Grid, Tile, TILES};
+use crate::{BATTERY, FIRE};
+
+/// Charge of a tile at the front of a spark.
+pub const SPARK: u8 = 3;
+/// Ticks between two sparks coming out of a battery.
+const BATTERY_PERIOD: u64 = 12;
+const SPARK_IGNITE_CHANCE: f32 = 0.3;
+
+const ORTHOGONAL: [Neighbour; 4] = [Neighbour::Up, Neighbour::Down, Neighbour::Left, Neighbour::Right];
+
+/// Brightens `colour` the more `charge` there is, so you can watch sparks go.
+pub fn glow(colour: (u8, u8, u8), charge: u8) -> (u8, u8, u8) {
+    let t = charge.min(SPARK) as f32 / SPARK as f32;
+    let mix = |c: u8, to: u8| crate::vec2::lerp(c as f32, to as f32, t * 0.8) as u8;
+    (mix(colour.0, 255), mix(colour.1, 250), mix(colour.2, 160))
+}
+
+impl Grid {
+    pub(super) fn propagate_charge(&mut self) {
+        if self.ticks.is_multiple_of(BATTERY_PERIOD) {
+            for t in self.grid.iter_mut().filter(|t| t.index == BATTERY && t.charge == 0) {
+                t.charge = SPARK;
+            }
+        }
+
+        let (w, h) = self.get_wh();
+        let fronts: Vec<_> = (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .filter(|p| self[*p].charge == SPARK)
+            .collect();
+
+        for t in &mut self.grid {
+            t.charge = t.charge.saturating_sub(1);
+        }
+
+        let powered = std::mem::take(&mut self.powered);
+        for &(x, y) in &fronts {
+            for n in ORTHOGONAL {
+                let (dx, dy) = n.offset();
+                let Some(p) = self.resolve(x as isize + dx, y as isize + dy) else { continue; };
+                let id = &TILES[self[p].index];
+                if id.conductive && self[p].charge == 0 {
+                    self[p].charge = SPARK;
+                }
+                else if id.flammable && self.rng.chance(SPARK_IGNITE_CHANCE) {
+                    self[p] = Tile::new(FIRE);
+                }
+            }
+            // the spark might have been moved or burnt away by another one
+            if TILES[self[(x, y)].index].conductive {
+                self.apply_rules(x, y, &powered[self[(x, y)].index]);
+            }
+        }
+        self.powered = powered;
+    }
+}
@@ -0,0 +1,198 @@
+//! Explosions, and the loose tiles they send flying.
+//!
+//! An explosion has a `power`, which is both how far it reaches (in tiles) and how hard it hits
+//! at its centre. The force falls off linearly to nothing at the edge, and a tile is blown away
+//! wherever the force is at least its `TileId::hardness`, so stone only breaks close in while
+//! sand goes a long way. Loose stuff that's blown away is thrown as a `Particle`, anything else
+//! is destroyed, and what's flammable catches fire.
+use super::neighbour::Neighbour;
+use super::{Grid, Tile, TileIndex, TILES, TILE_HEIGHT, TILE_WIDTH};
+use crate::vec2::Vec2;
+use crate::{AIR, FIRE, LAVA};
+use sdl2::rect::Rect;
+
+/// Speed (tiles per tick) a particle is thrown at, per unit of force left where it was.
+const FLING_SPEED   : f32 = 0.6;
+/// Particles are thrown a bit upwards on top of away from the blast, it looks better.
+const FLING_LIFT    : f32 = 0.8;
+const PARTICLE_GRAVITY: f32 = 0.25;
+/// Odds of the air close to the centre of the blast catching fire.
+const FIREBALL_CHANCE: f32 = 0.5;
+/// How hard an explosion shoves things, per unit of force left where they are.
+const IMPULSE       : f32 = 2.5;
+
+/// A tile flying through the air, it goes back into the grid where it lands.
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    /// In tiles, not pixels.
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub tile: Tile,
+}
+
+/// Something blew up, kept around so the things that aren't tiles can react to it.
+#[derive(Clone, Copy, Debug)]
+pub struct Explosion {
+    /// Centre of the blast, in pixels.
+    pub centre: Vec2,
+    pub power: f32,
+}
+
+impl Explosion {
+    /// The shove the blast gives to something at `p` (in pixels).
+    pub fn impulse_at(&self, p: Vec2) -> Vec2 {
+        let d = p - self.centre;
+        let dist = d.dist(&Vec2::ZERO) / TILE_WIDTH as f32;
+        let force = self.power - dist;
+        if force <= 0.0 { return Vec2::ZERO; }
+        let dir = if dist > 0.0 { d * (1.0 / (dist * TILE_WIDTH as f32)) } else { Vec2(0.0, -1.0) };
+        dir * (force * IMPULSE)
+    }
+}
+
+impl Grid {
+    /// Every cell within `radius` tiles of `(x, y)` along with how far away it is, going through
+    /// wrapping edges and leaving out whatever is off the grid.
+    pub fn radius(&self, x: usize, y: usize, radius: f32) -> Vec<((usize, usize), f32)> {
+        let r = radius.ceil() as isize;
+        let mut res = vec![];
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                if dist > radius { continue; }
+                if let Some(p) = self.resolve(x as isize + dx, y as isize + dy) {
+                    res.push((p, dist));
+                }
+            }
+        }
+        res
+    }
+
+    /// Blows up `(x, y)`, setting off any other explosives caught in it.
+    pub fn explode(&mut self, x: usize, y: usize, power: f32) {
+        let mut queue = vec![(x, y, power)];
+        while let Some((x, y, power)) = queue.pop() {
+            self.explosions.push(Explosion {
+                centre: Vec2((x as f32 + 0.5) * TILE_WIDTH as f32, (y as f32 + 0.5) * TILE_HEIGHT as f32),
+                power,
+            });
+
+            for ((cx, cy), dist) in self.radius(x, y, power) {
+                let force = power - dist;
+                let tile = self[(cx, cy)];
+                let id = &TILES[tile.index];
+
+                if let (Some(p), true) = (id.explosive, (cx, cy) != (x, y)) {
+                    self[(cx, cy)] = Tile::default();
+                    queue.push((cx, cy, p));
+                    continue;
+                }
+                if tile.index == AIR {
+                    if dist < power / 2.0 && self.rng.chance(FIREBALL_CHANCE) {
+                        self[(cx, cy)] = Tile::new(FIRE);
+                    }
+                    continue;
+                }
+                if force < id.hardness {
+                    if id.flammable { self[(cx, cy)] = Tile::new(FIRE); }
+                    continue;
+                }
+
+                self[(cx, cy)] = Tile::default();
+                // loose stuff, heavier than air so not gases, gets thrown
+                if !id.rules.is_empty() && id.density > TILES[AIR].density {
+                    let away = Vec2(cx as f32 - x as f32, cy as f32 - y as f32);
+                    let away = if dist > 0.0 { away * (1.0 / dist) } else { Vec2::ZERO };
+                    let jitter = Vec2(self.rng.unit() - 0.5, self.rng.unit() - 0.5);
+                    self.particles.push(Particle {
+                        pos: Vec2(cx as f32, cy as f32),
+                        vel: (away + jitter + Vec2(0.0, -FLING_LIFT)) * (force * FLING_SPEED),
+                        tile,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Fills the air within `radius` tiles of `(x, y)` with `tile`, a splash of water or a burst of
+    /// flame. Smoke, steam and fire count as air, so water puts fires out, and fire sets light to
+    /// anything flammable it reaches as well.
+    pub fn splash(&mut self, x: usize, y: usize, radius: f32, tile: TileIndex) {
+        for (p, _) in self.radius(x, y, radius) {
+            let id = &TILES[self[p].index];
+            if !id.solid && id.density <= TILES[AIR].density {
+                self[p] = Tile::new(tile);
+            }
+            else if tile == FIRE && id.flammable {
+                self[p] = Tile::new(FIRE);
+            }
+        }
+    }
+
+    /// Blows up explosive tiles touching fire, lava or a spark.
+    pub(super) fn check_fuse(&mut self, x: usize, y: usize, power: f32) {
+        let lit = [Neighbour::Up, Neighbour::Down, Neighbour::Left, Neighbour::Right].iter().any(|n| {
+            let (dx, dy) = n.offset();
+            self.resolve(x as isize + dx, y as isize + dy)
+                .map(|p| self[p].index == FIRE || self[p].index == LAVA || self[p].charge > 0)
+                .unwrap_or(false)
+        });
+        if lit || self[(x, y)].charge > 0 {
+            self.explode(x, y, power);
+        }
+    }
+
+    /// Moves every particle along its path, dropping it into the last free cell before whatever
+    /// it hits.
+    pub(super) fn update_particles(&mut self) {
+        let particles = std::mem::take(&mut self.particles);
+        for mut particle in particles {
+            particle.vel.1 += PARTICLE_GRAVITY;
+            let next = particle.pos + particle.vel;
+
+            let mut landed = None;
+            let mut last = None;
+            for pt in Vec2::linef32(particle.pos, next).into_iter().chain(std::iter::once(next)) {
+                let (px, py) = (pt.0.round() as isize, pt.1.round() as isize);
+                match self.locate(px, py) {
+                    Ok(p) if self[p].index == AIR => last = Some(p),
+                    Ok(_) | Err(super::Edge::Wall) => { landed = Some(last); break; }
+                    Err(_) => { landed = Some(None); break; }
+                }
+            }
+
+            match landed {
+                Some(Some(p)) => { self[p] = particle.tile; }
+                // landed with nowhere to go, or gone into the void
+                Some(None) => (),
+                None => {
+                    particle.pos = self.wrap_tiles(next);
+                    self.particles.push(particle);
+                }
+            }
+        }
+    }
+
+    /// Like `wrap_point` but for a position in tiles.
+    fn wrap_tiles(&self, p: Vec2) -> Vec2 {
+        let px = Vec2(p.0 * TILE_WIDTH as f32, p.1 * TILE_HEIGHT as f32);
+        let px = self.wrap_point(px);
+        Vec2(px.0 / TILE_WIDTH as f32, px.1 / TILE_HEIGHT as f32)
+    }
+
+    pub(super) fn draw_particles(&self, canvas: &mut crate::Canvas2) {
+        for p in &self.particles {
+            let rect = Rect::new(
+                (p.pos.0 * TILE_WIDTH as f32) as i32, (p.pos.1 * TILE_HEIGHT as f32) as i32,
+                TILE_WIDTH as u32, TILE_HEIGHT as u32,
+            );
+            canvas.set_draw_color(TILES[p.tile.index].colour.into());
+            let _ = canvas.fill_rect(rect);
+        }
+    }
+
+    /// Explosions since the last time this was called.
+    pub fn take_explosions(&mut self) -> Vec<Explosion> {
+        std::mem::take(&mut self.explosions)
+    }
+}
@@ -0,0 +1,122 @@
+//! Little hand built scenes for eyeballing the simulation, loaded from debug keys in `main`, and
+//! for the tests to check it does what it should.
+use super::{Grid, Tile, TileIndex};
+
+impl Grid {
+    /// Clears the grid and builds a diagonal line of `wall` going down to the right, where every
+    /// tile only touches the next one by its corner, closed off by a straight wall on the right,
+    /// with `material` resting on top of it. Materials that slip should leak through the cracks
+    /// under the line, the rest should stay put.
+    pub fn load_diagonal_crack(&mut self, wall: TileIndex, material: TileIndex) {
+        self.clear();
+        let (w, h) = self.get_wh();
+        let len = w.min(h) / 2;
+        let (x0, y0) = (w / 2 - len / 2, h / 2 - len / 2);
+
+        for i in 0..len {
+            self[(x0 + i, y0 + i)] = Tile::new(wall);
+            self[(x0 + len, y0 + i)] = Tile::new(wall);
+        }
+
+        // a triangle of material filling the space above the line
+        for i in 1..len {
+            for x in x0 + i..x0 + len {
+                self[(x, y0 + i - 1)] = Tile::new(material);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{rule::symbol_index, Boundary, Edge};
+    use crate::{GRAVEL, SAND, SMOKE, STONE, TILES, WATER};
+
+    const SIZE: usize = 30;
+    /// Tiles of material a crack on a `SIZE` grid starts out with, the triangle above a line
+    /// half as long as the grid.
+    const PILE: usize = (SIZE / 2) * (SIZE / 2 - 1) / 2;
+
+    /// How many tiles of `material` there are above the line of a diagonal crack, and how many
+    /// below it, after it's been left to run for a while. The grid is walled in so whatever gets
+    /// through stays where it can be counted.
+    fn leak(material: TileIndex) -> (usize, usize) {
+        let mut grid = Grid::new(SIZE, SIZE).unwrap();
+        grid.seed(1);
+        grid.set_boundary(Boundary::all(Edge::Wall));
+        grid.load_diagonal_crack(STONE, material);
+        for _ in 0..100 {
+            grid.update().unwrap();
+        }
+        let start = (SIZE / 2 - SIZE / 4) as isize;
+        let (above, below): (Vec<_>, Vec<_>) = (0..SIZE)
+            .flat_map(|y| (0..SIZE).map(move |x| (x, y)))
+            .filter(|&p| grid[p].index == material)
+            .partition(|&(x, y)| x as isize - start > y as isize - start);
+        (above.len(), below.len())
+    }
+
+    #[test]
+    fn sand_leaks_through_the_crack() {
+        let (above, below) = leak(SAND);
+        assert!(above < PILE && below > 0, "{} grains of sand above the crack and {} below", above, below);
+    }
+
+    #[test]
+    fn gravel_gets_stuck_on_the_crack() {
+        assert_eq!(leak(GRAVEL), (PILE, 0));
+    }
+
+    /// A walled in grid with a row of `material` along the bottom.
+    fn gas(material: TileIndex) -> Grid {
+        let mut grid = Grid::new(SIZE, SIZE).unwrap();
+        grid.seed(1);
+        grid.set_boundary(Boundary::all(Edge::Wall));
+        for x in 0..SIZE {
+            grid[(x, SIZE - 1)] = Tile::new(material);
+        }
+        grid
+    }
+
+    /// Where there's `material`, and how high up it is on average in tiles from the bottom.
+    fn find(grid: &Grid, material: TileIndex) -> (usize, f32) {
+        let ys: Vec<_> = (0..SIZE)
+            .flat_map(|y| (0..SIZE).map(move |x| (x, y)))
+            .filter(|&p| grid[p].index == material)
+            .map(|(_, y)| (SIZE - 1 - y) as f32)
+            .collect();
+        (ys.len(), ys.iter().sum::<f32>() / ys.len().max(1) as f32)
+    }
+
+    fn run(grid: &mut Grid, ticks: u32) {
+        for _ in 0..ticks {
+            grid.update().unwrap();
+        }
+    }
+
+    #[test]
+    fn smoke_rises_and_goes() {
+        let mut grid = gas(SMOKE);
+        let lifetime = TILES[SMOKE].lifetime.unwrap();
+        run(&mut grid, 30);
+        let (smoke, height) = find(&grid, SMOKE);
+        assert_eq!(smoke, SIZE);
+        assert!(height > 5.0, "smoke only got {} up", height);
+        run(&mut grid, lifetime - 31);
+        assert_eq!(find(&grid, SMOKE).0, SIZE);
+        run(&mut grid, lifetime);
+        assert_eq!(find(&grid, SMOKE).0, 0);
+    }
+
+    #[test]
+    fn steam_turns_back_into_water() {
+        let steam = symbol_index('v').unwrap();
+        let mut grid = gas(steam);
+        run(&mut grid, 2 * TILES[steam].lifetime.unwrap());
+        assert_eq!(find(&grid, steam).0, 0);
+        let (water, height) = find(&grid, WATER);
+        assert_eq!(water, SIZE);
+        assert!(height < 1.0, "water is {} up", height);
+    }
+}
@@ -0,0 +1,121 @@
+//! Structural integrity, stone and wood that nothing is holding up come down.
+//!
+//! Support spreads out from anchors through anything solid that's touching. Anchors are the walls
+//! around the grid, the bottom row unless it wraps (the void under it is still the floor), solid
+//! tiles that don't move by themselves and aren't `TileId::structural` (metal, taps, that sort of
+//! thing, think of them as bolted down) and rigid bodies, which look after themselves. Any
+//! structural tile the support doesn't reach collapses, either crumbling into its
+//! `TileId::breaks_into` or falling as a rigid body.
+use super::{Edge, Grid, Tile, TILES};
+
+/// Biggest chunk of unsupported stuff that falls as a single body, anything bigger stays hanging
+/// there until something breaks it up.
+const MAX_FALLING_TILES : usize = 600;
+
+impl Grid {
+    pub fn integrity(&self) -> bool {
+        self.integrity
+    }
+
+    pub fn set_integrity(&mut self, integrity: bool) {
+        self.integrity = integrity;
+    }
+
+    /// Whether the tile at `p` holds up whatever's touching it no matter what.
+    fn is_anchor(&self, p: (usize, usize)) -> bool {
+        let id = &TILES[self[p].index];
+        id.solid && !id.structural && id.rules.is_empty()
+    }
+
+    /// Which cells are connected to an anchor, indexed like `self.grid`.
+    fn supported(&self) -> Vec<bool> {
+        let (w, h) = self.get_wh();
+        let mut supported = vec![false; w * h];
+        let mut todo = vec![];
+        let mut seed = |p: (usize, usize), todo: &mut Vec<(usize, usize)>| {
+            if !supported[p.1 * w + p.0] {
+                supported[p.1 * w + p.0] = true;
+                todo.push(p);
+            }
+        };
+
+        for p in self.bodies.iter().flat_map(|b| b.stamped.iter()) {
+            seed(*p, &mut todo);
+        }
+        for y in 0..h {
+            for x in 0..w {
+                if !TILES[self[(x, y)].index].solid { continue; }
+                let walled = [(0, 1), (1, 0), (0, -1), (-1, 0)].into_iter()
+                    .any(|(dx, dy)| self.locate(x as isize + dx, y as isize + dy) == Err(Edge::Wall));
+                let floor = y + 1 == h && self.boundary.bottom != Edge::Wrap;
+                if walled || floor || self.is_anchor((x, y)) {
+                    seed((x, y), &mut todo);
+                }
+            }
+        }
+
+        while let Some((x, y)) = todo.pop() {
+            for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+                let Some(p) = self.resolve(x as isize + dx, y as isize + dy) else { continue; };
+                if TILES[self[p].index].solid {
+                    seed(p, &mut todo);
+                }
+            }
+        }
+        supported
+    }
+
+    /// Brings down every structural tile that's lost its support.
+    pub(super) fn check_integrity(&mut self) {
+        let (w, h) = self.get_wh();
+        let mut supported = self.supported();
+        for y in 0..h {
+            for x in 0..w {
+                let id = &TILES[self[(x, y)].index];
+                if !id.structural || supported[y * w + x] { continue; }
+                match id.breaks_into {
+                    Some(debris) => {
+                        self[(x, y)] = Tile::new(debris);
+                        self[(x, y)].updated = true;
+                    }
+                    None => if let Some(cells) = self.connected(x, y, MAX_FALLING_TILES) {
+                        // the body's drawn straight back in where it was, don't lift it twice
+                        for &(x, y) in &cells {
+                            supported[y * w + x] = true;
+                        }
+                        self.lift_body(cells, false);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::STONE;
+
+    /// How much of a 3x3 block of stone `y` tiles up from the floor of a 10x10 grid, with the
+    /// default boundary, is still stone after one go of integrity.
+    fn block(y: usize) -> usize {
+        let mut grid = Grid::new(10, 10).unwrap();
+        for by in 7 - y..10 - y {
+            for bx in 3..6 {
+                grid[(bx, by)] = Tile::new(STONE);
+            }
+        }
+        grid.check_integrity();
+        (0..10).flat_map(|y| (0..10).map(move |x| (x, y))).filter(|&p| grid[p].index == STONE).count()
+    }
+
+    #[test]
+    fn floor_holds_stone_up() {
+        assert_eq!(block(0), 9);
+    }
+
+    #[test]
+    fn floating_stone_crumbles() {
+        assert_eq!(block(3), 0);
+    }
+}
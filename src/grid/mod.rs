@@ -1,11 +1,29 @@
 use super::{Canvas2, TILES, WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::rng::Rng;
+use crate::vec2::Vec2;
 use sdl2::rect::Rect;
+use std::collections::HashMap;
 use std::fmt;
 
 pub mod neighbour;
-pub use neighbour::*;
-
-pub const CURS_SMALLEST : usize = 1;
+pub mod fixtures;
+pub mod rule;
+use rule::CompiledRule;
+pub mod automaton;
+pub use automaton::Mode;
+pub mod electricity;
+pub mod sources;
+pub mod boundary;
+pub use boundary::{Boundary, Edge};
+pub mod explosion;
+use explosion::{Explosion, Particle};
+pub mod body;
+use body::RigidBody;
+pub mod integrity;
+pub mod obstacle;
+pub mod world;
+pub mod chunk;
+pub mod path;
 
 pub const TILE_WIDTH    : usize = 10;
 pub const TILE_HEIGHT   : usize = 10;
@@ -13,6 +31,10 @@ pub const TILE_HEIGHT   : usize = 10;
 pub const MAX_GRID_WIDTH: usize = 800;
 pub const MAX_GRID_HEIGHT: usize = 600;
 
+/// Odds per tick that a tile which has outlived its lifetime actually decays, so a puff of smoke
+/// thins out instead of vanishing all at once.
+const DECAY_CHANCE      : f32 = 0.05;
+
 pub type Result<T> = ::std::result::Result<T, GridResult>;
 
 #[derive(Debug, thiserror::Error)]
@@ -20,32 +42,48 @@ pub enum GridResult {
     GridTooLarge,
     OOB,
     Obstructed,
+    /// A material's rule doesn't make sense, says which and why.
+    BadRule(String),
+    /// A saved world doesn't fit the grid or has something odd in it.
+    BadWorld(String),
 }
 
 impl fmt::Display for GridResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "go and fuck yourself")
+        match self {
+            GridResult::BadRule(why) => write!(f, "bad rule, {}", why),
+            GridResult::BadWorld(why) => write!(f, "bad world, {}", why),
+            _ => write!(f, "go and fuck yourself"),
+        }
     }
 }
 
 
 
+/// What's at some position, which might be past the edge of the grid.
 #[derive(Clone, Copy, Debug)]
-pub enum TileIdType {
-    Static,
-    Dynamic,
+pub enum Sample {
+    Tile(Tile),
+    Wall,
+    Void,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Tile {
     index: TileIndex,
     updated: bool,
+    /// Ticks this tile has been alive for, only counted for tiles with a lifetime.
+    age: u32,
+    /// Scratch space for whatever the tile needs to remember, the ants keep their heading here.
+    state: u8,
+    /// How electrified a conductive tile is, see `electricity`.
+    charge: u8,
 }
 
 impl Tile {
     fn new(index: TileIndex) -> Self {
         Self {
-            index, updated: false
+            index, updated: false, age: 0, state: 0, charge: 0
         }
     }
 }
@@ -54,7 +92,10 @@ impl Default for Tile {
     fn default() -> Self {
         Tile {
             index: 0, 
-            updated: false
+            updated: false,
+            age: 0,
+            state: 0,
+            charge: 0,
         }
     }
 }
@@ -64,16 +105,56 @@ pub struct Grid {
     grid: Vec<Tile>,
     width: usize,
     height: usize,
+    rng: Rng,
+    /// Compiled rules of every material, indexed like `TILES`.
+    rules: Vec<Vec<CompiledRule>>,
+    /// Same again for `TileId::powered`.
+    powered: Vec<Vec<CompiledRule>>,
+    mode: Mode,
+    /// Sandbox ticks since the grid was made.
+    ticks: u64,
+    boundary: Boundary,
+    particles: Vec<Particle>,
+    explosions: Vec<Explosion>,
+    bodies: Vec<RigidBody>,
+    /// Whether unsupported stone and wood come down, see `integrity`.
+    integrity: bool,
+    /// Cells covered by obstacles and which obstacle covers them, see `obstacle`.
+    obstacles: HashMap<(usize, usize), usize>,
+    pushes: Vec<Vec2>,
+    /// Scratch space for `apply_rules`, kept so there isn't a new one for every tile every tick.
+    writes: Vec<Write>,
+}
+
+/// A tile a rule is about to put down.
+struct Write {
+    p: (usize, usize),
+    tile: Tile,
+    /// Which way it's going from the centre, for pushing anything in the way.
+    dir: (isize, isize),
 }
 
-type TileIndex = usize;
+pub type TileIndex = usize;
 
 impl Grid { 
     pub fn new(w: usize, h: usize) -> Result<Self> {
         Grid::assert_wh(w, h)?;
         Ok(Grid {
             grid: [Tile::default()].repeat(w*h),
-            width: w, height: h
+            width: w, height: h,
+            rng: Rng::from_time(),
+            rules: rule::compile_all(|t| t.rules)?,
+            powered: rule::compile_all(|t| t.powered)?,
+            mode: Mode::default(),
+            ticks: 0,
+            boundary: Boundary::default(),
+            particles: vec![],
+            explosions: vec![],
+            bodies: vec![],
+            integrity: false,
+            obstacles: HashMap::new(),
+            pushes: vec![],
+            writes: vec![],
         })
     }
 
@@ -103,32 +184,32 @@ impl Grid {
         for y in 0..h {
             for x in 0..w {
                 let rect = Rect::new(x as i32 * TILE_WIDTH as i32, y as i32 * TILE_HEIGHT as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32);
-                canvas.set_draw_color(if TILES.len()-1 >= self[(x, y)].index { TILES[self[(x, y)].index].colour.into() } else { (255, 0, 0).into() });
+                let colour = if TILES.len()-1 >= self[(x, y)].index { TILES[self[(x, y)].index].colour } else { (255, 0, 0) };
+                canvas.set_draw_color(electricity::glow(colour, self[(x, y)].charge).into());
                 let _ = canvas.fill_rect(rect);
             }
         }
+        self.draw_particles(canvas);
     }
 
+    /// Rects of every solid tile overlapping `rect`, both in pixels. Walls around the grid count
+    /// as solid, and tiles across an edge that wraps are given where they'd be if the grid carried
+    /// on, so they line up with `rect`.
     pub fn get_cols_in_rect(&self, rect: Rect) -> Option<Vec<Rect>> {
-        let (w, h) = self.get_wh();
-        if rect.x < 0 || rect.y < 0 || rect.x as usize / TILE_WIDTH >= w || rect.y as usize / TILE_HEIGHT >= h { return None; }
-
-        let x_range = rect.x as usize / TILE_WIDTH..(rect.x as usize + rect.w as usize + TILE_WIDTH - 1) / TILE_WIDTH;
-        let y_range = rect.y as usize / TILE_HEIGHT..(rect.y as usize + rect.h as usize + TILE_HEIGHT - 1) / TILE_HEIGHT;
+        let (tw, th) = (TILE_WIDTH as i32, TILE_HEIGHT as i32);
+        let x_range = rect.x.div_euclid(tw)..(rect.x + rect.w + tw - 1).div_euclid(tw);
+        let y_range = rect.y.div_euclid(th)..(rect.y + rect.h + th - 1).div_euclid(th);
 
         let mut res = vec!();
 
         for y in y_range {
             for x in x_range.clone() {
-                self.assert_inbounds(x as isize, y as isize).ok()?;
-                if TILES[self[(x, y)].index].solid {
-                    let rect = Rect::new(
-                        x as i32 * TILE_WIDTH as i32, 
-                        y as i32 * TILE_HEIGHT as i32, 
-                        TILE_WIDTH as u32,
-                        TILE_HEIGHT as u32,
-                    );
-                    res.push(rect);
+                let solid = match self.locate(x as isize, y as isize) {
+                    Ok(p) => TILES[self[p].index].solid,
+                    Err(edge) => edge == Edge::Wall,
+                };
+                if solid {
+                    res.push(Rect::new(x * tw, y * th, TILE_WIDTH as u32, TILE_HEIGHT as u32));
                 }
             }
         }
@@ -136,74 +217,159 @@ impl Grid {
         Some(res)
     }
 
-    fn find_free(&self, x: usize, y: usize, neighbours: &[Neighbour]) -> Result<(usize, usize)> {
-        let (w, h) = self.get_wh();
-        if x >= w || y >= h { return Err(GridResult::OOB.into()); }
-
-        if neighbours == &[Neighbour::Ident] {
-            return neighbours[0].check_free(&self, x, y);
-        }
-        
-        let mut oob = true;
-        for n in neighbours {
-            match n.check_free(&self, x, y) {
-                Ok(r) => {
-                    if n.components().contains(&Neighbour::Left) || n.components().contains(&Neighbour::Right) {
-                        return Ok(r);
-                    }
-                    else { return Ok(r); }
-                }
-                Err(GridResult::OOB) => {
-                    return Err(GridResult::OOB);
-                    // oob &= true;
-                }
-                _ => {
-                    oob &= false;
+    /// Material of every tile overlapping `rect` (in pixels) and how many pixels of `rect` it
+    /// covers. Wraps around like `get_cols_in_rect`, anything past a wall or in the void is left
+    /// out.
+    pub fn tiles_in_rect(&self, rect: Rect) -> Vec<(TileIndex, u32)> {
+        let (tw, th) = (TILE_WIDTH as i32, TILE_HEIGHT as i32);
+        let x_range = rect.x.div_euclid(tw)..(rect.x + rect.w + tw - 1).div_euclid(tw);
+        let y_range = rect.y.div_euclid(th)..(rect.y + rect.h + th - 1).div_euclid(th);
+
+        let mut res = vec![];
+        for y in y_range {
+            for x in x_range.clone() {
+                let Ok(p) = self.locate(x as isize, y as isize) else { continue; };
+                let cell = Rect::new(x * tw, y * th, TILE_WIDTH as u32, TILE_HEIGHT as u32);
+                if let Some(overlap) = rect.intersection(cell) {
+                    res.push((self[p].index, overlap.width() * overlap.height()));
                 }
             }
-            //if let Some(r) = n.check_free(&self.grid, x, y) { return Ok(r) };
         }
+        res
+    }
 
-        if oob == true {
-            Err(GridResult::OOB)
-        }
-        else {
-            Err(GridResult::Obstructed.into())
-        }
+    /// Material of the tile at `(x, y)`, `None` off the grid.
+    pub fn index_at(&self, x: usize, y: usize) -> Option<TileIndex> {
+        self.assert_inbounds(x as isize, y as isize).ok()?;
+        Some(self[(x, y)].index)
+    }
+
+    /// Starts the random numbers over from `seed`, the same seed, world and input always play
+    /// out the same way.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
     }
 
-    fn swap(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
-        let prev = self[(x2, y2)].clone();
-        self[(x2, y2)] = self[(x1, y1)].clone();
-        self[(x1, y1)] = prev;
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
     }
 
     pub fn update(&mut self) -> Result<()> {
+        if self.mode != Mode::Sandbox {
+            self.step_automaton();
+            return Ok(());
+        }
         for t in &mut self.grid {
             t.updated = false;
         }
+        self.update_bodies();
+        if self.integrity {
+            self.check_integrity();
+        }
+        // the rules have to come out of self so they can be read while the grid is written to
+        let rules = std::mem::take(&mut self.rules);
         let (w, h) = self.get_wh();
         for y in 0..h {
             for x in 0..w {
-                let tile_id = &TILES[self[(x, y)].index];
-                if tile_id.gravity && !self[(x, y)].updated {
-                    self.update_static_tile(x, y, &tile_id)?;
+                if self[(x, y)].updated { continue; }
+                let index = self[(x, y)].index;
+                let tile_id = &TILES[index];
+                if let Some(lifetime) = tile_id.lifetime {
+                    self[(x, y)].age += 1;
+                    if self[(x, y)].age >= lifetime && self.rng.chance(DECAY_CHANCE) {
+                        self[(x, y)] = Tile::new(tile_id.decays_into);
+                        self[(x, y)].updated = true;
+                        continue;
+                    }
+                }
+                if tile_id.emits.is_some() || tile_id.sink {
+                    self.update_source(x, y, tile_id);
                 }
+                if let Some(power) = tile_id.explosive {
+                    self.check_fuse(x, y, power);
+                    if self[(x, y)].index != index { continue; }
+                }
+                self.apply_rules(x, y, &rules[index]);
             }
         }
+        self.rules = rules;
+        self.update_particles();
+        self.propagate_charge();
+        self.ticks += 1;
         Ok(())
     }
 
-    fn update_static_tile(&mut self, x: usize, y: usize, tile_id: &crate::TileId) -> Result<()> {
-        if let Ok((nx, ny)) = self.find_free(x, y, tile_id.neighbours) {
-            self.swap(x, y, nx, ny);
-            self[(nx, ny)].updated = true;
+    /// Applies the first of `rules` that matches around `(x, y)`. If obstacles got in the way of
+    /// everything that did, the first of them gets pushed.
+    fn apply_rules(&mut self, x: usize, y: usize, rules: &[CompiledRule]) {
+        let me = self[(x, y)].index;
+        let slip = TILES[me].slip;
+        let mut writes = std::mem::take(&mut self.writes);
+        let mut blocked = None;
+        'rules: for rule in rules {
+            if rule.chance < 1.0 && !self.rng.chance(rule.chance) { continue; }
+
+            let orientations = rule.orientations();
+            let start = self.rng.below(orientations.len());
+            for o in (0..orientations.len()).map(|i| orientations[(start + i) % orientations.len()]) {
+                let at = |c: &rule::Cell| {
+                    let (dx, dy) = o(c.dx, c.dy);
+                    (x as isize + dx, y as isize + dy)
+                };
+
+                let mut corners = None;
+                let matched = rule.cells.iter().all(|c| {
+                    let (cx, cy) = at(c);
+                    let tile = self.sample(cx, cy);
+                    if c.input == rule::In::Corner {
+                        let open = rule::matches(rule::In::Empty, tile, me);
+                        corners = Some(corners.unwrap_or(false) || open);
+                    }
+                    rule::matches(c.input, tile, me)
+                });
+                if !matched || (!slip && corners == Some(false)) { continue; }
+
+                // read everything before writing anything, so swaps don't trip over themselves
+                let centre = self[(x, y)];
+                let displaced = rule.cells.iter()
+                    .find(|c| c.output == rule::Out::Centre)
+                    .and_then(|c| {
+                        let (cx, cy) = at(c);
+                        match self.sample(cx, cy) { Sample::Tile(t) => Some(t), _ => None }
+                    })
+                    .unwrap_or_default();
+                writes.clear();
+                writes.extend(rule.cells.iter()
+                    .filter_map(|c| {
+                        let tile = match c.output {
+                            rule::Out::Keep => return None,
+                            rule::Out::Centre => centre,
+                            rule::Out::Displaced => displaced,
+                            rule::Out::Set(index) => Tile::new(index),
+                        };
+                        let (cx, cy) = at(c);
+                        Some(Write { p: self.resolve(cx, cy)?, tile, dir: (cx - x as isize, cy - y as isize) })
+                    }));
+                if let Some(block) = writes.iter().find_map(|w| Some((self.blocker(w.p, &w.tile)?, w.dir))) {
+                    blocked.get_or_insert(block);
+                    continue;
+                }
+                for &Write { p, tile, .. } in &writes {
+                    self[p] = tile;
+                    self[p].updated = true;
+                }
+                blocked = None;
+                break 'rules;
+            }
         }
-        else if let Err(GridResult::OOB) = self.find_free(x, y, tile_id.neighbours) {
-            self.set(x, y, 0, CURS_SMALLEST)?;
+        self.writes = writes;
+        if let Some((i, dir)) = blocked {
+            self.push_obstacle(i, dir);
         }
-        else {} // do nothing, the block won't move by default
-        Ok(())
     }
 
     pub fn set(&mut self, mut x: usize, mut y: usize, tile: TileIndex, size: usize) -> Result<()> {
@@ -229,6 +395,9 @@ impl Grid {
         for e in &mut self.grid {
             *e = Tile::default();
         }
+        self.particles.clear();
+        self.bodies.clear();
+        self.pushes.clear();
     }
 }
 
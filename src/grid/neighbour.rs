@@ -1,6 +1,12 @@
 use super::TILES;
-use super::{GridResult, Grid};
+use super::{Edge, GridResult, Grid};
 
+/// A direction a tile can try to move in.
+///
+/// Diagonals are blocked by corners: if both of their orthogonal components are solid the tile is
+/// looking at a diagonal crack between two touching tiles, and it won't squeeze through it. Fine
+/// stuff like sand or water trickles through those cracks, but only when it moves by the rules in
+/// `grid::rule`, whose corners go by `TileId::slip`.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Neighbour {
     /// Or, 'No direction', checks the current cell during collision checks.
@@ -9,82 +15,63 @@ pub enum Neighbour {
     Up,
     UpLeft,
     UpRight,
-    UpLeftSlip,
-    UpRightSlip,
     Down,
     DownLeft,
     DownRight,
-    DownLeftSlip,
-    DownRightSlip,
     Left,
     Right,
 }
 
 use Neighbour::*;
 pub const NOSLIP_NEIGHBOURS: &[Neighbour] = &[Ident, Up, UpLeft, UpRight, Down, DownLeft, DownRight, Left, Right];
-pub const ALL_NEIGHBOURS: &[Neighbour] = &[Ident, Up, UpLeft, UpRight, UpRightSlip, UpLeftSlip, Down, DownLeft, DownLeftSlip, DownRight, DownRightSlip, Left, Right];
 
 impl Neighbour {
+    /// Where this direction goes from `(x, y)` if the tile there isn't solid. Corners block the
+    /// plain diagonals as described on [`Neighbour`], and the grid's `Boundary` decides what's past
+    /// the edges: walls are `Obstructed`, the void is `OOB` and wrapping edges are gone through.
     pub fn check_free(&self, grid: &Grid, x: usize, y: usize) -> Result<(usize, usize), GridResult> {
-        let (w, h) = grid.get_wh();
-        let (mx, my) = self.get_npos(x, y).ok_or(GridResult::OOB)?;
-        if mx >= w { return Err(GridResult::OOB.into()) };
-        if my >= h { return Err(GridResult::OOB.into()) };
-        let tile = &TILES[grid[(mx, my)].index];
-        //if grid[my][mx].index == grid[y][x].index { return Err(GridResult::Obstructed.into()); }
-        use Neighbour::*;
-        match self {
-            Ident | Up | Down | Left | Right | UpLeftSlip | UpRightSlip | DownRightSlip | DownLeftSlip => {
-                if !tile.solid { Ok((mx, my)) }
-                else { Err(GridResult::Obstructed.into()) }
+        let free = |n: &Neighbour| {
+            let (dx, dy) = n.offset();
+            match grid.locate(x as isize + dx, y as isize + dy) {
+                Ok(p) if !TILES[grid[p].index].solid => Ok(p),
+                Ok(_) | Err(Edge::Wall) => Err(GridResult::Obstructed),
+                Err(_) => Err(GridResult::OOB),
             }
-            _ => {
-                if tile.solid { Err(GridResult::Obstructed.into()) }
-                else { 
-                    let mut both_solid = true;
-                    for c in self.components() {
-                        let (mmx, mmy) = c.get_npos(x, y).ok_or(GridResult::OOB)?;
+        };
 
-                        if mmx >= w { return Err(GridResult::OOB.into()) };
-                        if mmy >= h { return Err(GridResult::OOB.into()) };
-                        let tile = &TILES[grid[(mmx, mmy)].index];
-                        both_solid &= tile.solid;
-                    }
-                    if both_solid { Err(GridResult::Obstructed.into()) }
-                    else { Ok((mx, my)) }
-                }
-            }
+        let p = free(self)?;
+        let corners = self.components();
+        if !corners.is_empty() && corners.iter().all(|c| matches!(free(c), Err(GridResult::Obstructed))) {
+            return Err(GridResult::Obstructed);
         }
+        Ok(p)
     }
 
-    /// Get the 'new' position of the x and y coordinates given, or the position after the
-    /// translation applied by this direction. 
-    ///
-    /// E.G: North => x, y + 1, etc...
-    fn get_npos(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+    /// The translation applied by this direction, as an offset that can go negative.
+    pub fn offset(&self) -> (isize, isize) {
         use Neighbour::*;
-        Some(match self {
-            Ident       => (x, y),
+        match self {
+            Ident       => (0, 0),
 
-            Down        => (x, y + 1),
-            Right       => (x + 1, y),
-            Up          => (x, y.checked_sub(1)?),
-            Left        => (x.checked_sub(1)?, y),
-            UpLeft | UpLeftSlip         => (x.checked_sub(1)?, y.checked_sub(1)?),
-            DownLeft | DownLeftSlip     => (x.checked_sub(1)?, y + 1),
-            UpRight | UpRightSlip       => (x + 1, y.checked_sub(1)?),
-            DownRight | DownRightSlip   => (x + 1, y + 1),
-        })
+            Down        => (0, 1),
+            Right       => (1, 0),
+            Up          => (0, -1),
+            Left        => (-1, 0),
+            UpLeft      => (-1, -1),
+            DownLeft    => (-1, 1),
+            UpRight     => (1, -1),
+            DownRight   => (1, 1),
+        }
     }
 
     pub fn components(&self) -> &[Neighbour] {
         use Neighbour::*;
         match self {
             // FIXME: is 'Ident' made up of every direction, or no direction?
-            DownLeft | DownLeftSlip => &[Down, Left],
-            DownRight | DownRightSlip => &[Down, Right],
-            UpRight | UpRightSlip => &[Up, Right],
-            UpLeft | UpLeftSlip => &[Up, Left],
+            DownLeft => &[Down, Left],
+            DownRight => &[Down, Right],
+            UpRight => &[Up, Right],
+            UpLeft => &[Up, Left],
             _ => &[]
         }
     }
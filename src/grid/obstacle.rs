@@ -0,0 +1,99 @@
+//! Things that aren't tiles but take up room in the grid, like the player.
+//!
+//! The grid doesn't move anything solid into a cell an obstacle covers, so sand piles up on the
+//! player instead of swapping into them. A tile that can't go anywhere because of that shoves the
+//! obstacle a bit the way it first wanted to go instead, which is how avalanches push the player
+//! around. Liquids and gases still flow in, the player can swim.
+use std::collections::HashMap;
+
+use sdl2::rect::Rect;
+
+use super::{Grid, Tile, TILE_HEIGHT, TILE_WIDTH, TILES};
+use crate::vec2::Vec2;
+
+/// How hard one blocked tile pushes, in pixels per tick.
+const PUSH_FORCE        : f32 = 1.0;
+
+impl Grid {
+    /// Sets where the obstacles are for the next update, as rects in pixels. Replaces the ones
+    /// from before along with any pushes they had, so take those first.
+    pub fn set_obstacles(&mut self, rects: &[Rect]) {
+        let (tw, th) = (TILE_WIDTH as i32, TILE_HEIGHT as i32);
+        self.obstacles = HashMap::new();
+        self.pushes = vec![Vec2::ZERO; rects.len()];
+        for (i, rect) in rects.iter().enumerate() {
+            for y in rect.y.div_euclid(th)..(rect.y + rect.h + th - 1).div_euclid(th) {
+                for x in rect.x.div_euclid(tw)..(rect.x + rect.w + tw - 1).div_euclid(tw) {
+                    if let Ok(p) = self.locate(x as isize, y as isize) {
+                        self.obstacles.insert(p, i);
+                    }
+                }
+            }
+        }
+    }
+
+    /// How much each obstacle got pushed since the obstacles were set or this was last called,
+    /// in the order they were set.
+    pub fn take_pushes(&mut self) -> Vec<Vec2> {
+        let zeros = vec![Vec2::ZERO; self.pushes.len()];
+        std::mem::replace(&mut self.pushes, zeros)
+    }
+
+    /// Which obstacle writing `tile` to `p` would shove something solid into, if any.
+    pub(super) fn blocker(&self, p: (usize, usize), tile: &Tile) -> Option<usize> {
+        let &i = self.obstacles.get(&p)?;
+        (TILES[tile.index].solid && !TILES[self[p].index].solid).then_some(i)
+    }
+
+    /// Shoves obstacle `i` the way a tile it's in the way of was going, `dir` being the move in
+    /// tiles.
+    pub(super) fn push_obstacle(&mut self, i: usize, dir: (isize, isize)) {
+        self.pushes[i] = self.pushes[i] + Vec2(dir.0 as f32, dir.1 as f32) * PUSH_FORCE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SAND, STONE};
+
+    /// A grid with sand at `(5, 4)` over an obstacle at `(5, 5)`, with stone either side of the
+    /// obstacle so the sand can't slide off it.
+    fn setup() -> Grid {
+        let mut grid = Grid::new(10, 10).unwrap();
+        grid[(5, 4)] = Tile::new(SAND);
+        grid[(4, 5)] = Tile::new(STONE);
+        grid[(6, 5)] = Tile::new(STONE);
+        grid
+    }
+
+    fn tile(x: usize, y: usize) -> Rect {
+        Rect::new((x * TILE_WIDTH) as i32, (y * TILE_HEIGHT) as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32)
+    }
+
+    #[test]
+    fn sand_piles_up_and_pushes() {
+        let mut grid = setup();
+        grid.set_obstacles(&[tile(1, 1), tile(5, 5)]);
+        grid.update().unwrap();
+        assert_eq!(grid[(5, 4)].index, SAND);
+        assert_eq!(grid.take_pushes(), vec![Vec2::ZERO, Vec2(0.0, PUSH_FORCE)]);
+        assert_eq!(grid.take_pushes(), vec![Vec2::ZERO, Vec2::ZERO]);
+        grid.update().unwrap();
+        grid.update().unwrap();
+        assert_eq!(grid.take_pushes(), vec![Vec2::ZERO, Vec2(0.0, 2.0 * PUSH_FORCE)]);
+    }
+
+    #[test]
+    fn setting_starts_over() {
+        let mut grid = setup();
+        grid.set_obstacles(&[tile(5, 5)]);
+        grid.update().unwrap();
+        grid.set_obstacles(&[tile(1, 1), tile(5, 5)]);
+        assert_eq!(grid.take_pushes(), vec![Vec2::ZERO, Vec2::ZERO]);
+        grid.set_obstacles(&[]);
+        grid.update().unwrap();
+        assert_eq!(grid[(5, 5)].index, SAND);
+        assert!(grid.take_pushes().is_empty());
+    }
+}
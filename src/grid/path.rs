@@ -0,0 +1,256 @@
+//! Finding the way across the grid for things that walk, like creatures.
+//!
+//! A walker is a block of cells, and it's standing somewhere when all of those are clear and
+//! there's something solid under at least one of them. Cells are named by where its feet are,
+//! the bottom left of the block. From where it's standing it can walk to the cell along, step up
+//! a tile or down off an edge, or jump: straight up as far as `Stride::jump`, then across as far
+//! as `Stride::leap`, then down onto whatever's there. Real jumps are arcs so they make it
+//! anywhere those can, and a bit further.
+//!
+//! What's solid comes from `get_cols_in_rect`, the same as for anything bumping into the grid, so
+//! walls around the edge count and wrapping edges carry on. Cells are in tiles and can be off the
+//! grid across a wrapping edge, like the rects there.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use sdl2::rect::Rect;
+
+use super::{Grid, TILE_HEIGHT, TILE_WIDTH, TILES};
+
+/// Cells looked at before giving up and settling for the closest one so far, so a walker boxed in
+/// on a big grid doesn't take all frame about it.
+const MAX_SEARCH        : usize = 4000;
+/// How dense something that isn't solid can be before walkers keep out of it, air and smoke are
+/// fine but they'd sink in water.
+const WADE_DENSITY      : f32 = 500.0;
+/// On top of the distance covered, so walkers only jump when walking won't do.
+const JUMP_COST         : u32 = 2;
+
+/// How big a walker is and how far it can get, in tiles.
+#[derive(Clone, Copy, Debug)]
+pub struct Stride {
+    pub w: i32,
+    pub h: i32,
+    /// Highest it jumps.
+    pub jump: i32,
+    /// Furthest across it jumps, from the top of the jump.
+    pub leap: i32,
+    /// Furthest it'll drop off an edge.
+    pub fall: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Open,
+    Solid,
+    /// Not solid but no place to be, it hurts, it's deep or it's the void.
+    Hazard,
+}
+
+/// The grid as a walker sees it, looked up as the search gets to it.
+struct Terrain<'a> {
+    grid: &'a Grid,
+    stride: Stride,
+    cells: HashMap<(i32, i32), Cell>,
+}
+
+impl Terrain<'_> {
+    fn cell(&mut self, x: i32, y: i32) -> Cell {
+        let grid = self.grid;
+        *self.cells.entry((x, y)).or_insert_with(|| {
+            let rect = Rect::new(x * TILE_WIDTH as i32, y * TILE_HEIGHT as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32);
+            if !grid.get_cols_in_rect(rect).unwrap_or_default().is_empty() {
+                return Cell::Solid;
+            }
+            // nothing at all is the void
+            match grid.tiles_in_rect(rect).first() {
+                Some(&(index, _)) if TILES[index].damage <= 0.0 && TILES[index].density < WADE_DENSITY => Cell::Open,
+                _ => Cell::Hazard,
+            }
+        })
+    }
+
+    /// Whether the walker fits with its feet at `(x, y)`.
+    fn fits(&mut self, x: i32, y: i32) -> bool {
+        for dy in 0..self.stride.h {
+            for dx in 0..self.stride.w {
+                if self.cell(x + dx, y - dy) != Cell::Open { return false; }
+            }
+        }
+        true
+    }
+
+    fn stands(&mut self, x: i32, y: i32) -> bool {
+        self.fits(x, y) && (0..self.stride.w).any(|dx| self.cell(x + dx, y + 1) == Cell::Solid)
+    }
+
+    /// Where the walker ends up dropping from `(x, y)`, if it's no further than `fall` and
+    /// there's room all the way down.
+    fn land(&mut self, x: i32, y: i32, fall: i32) -> Option<i32> {
+        for y in y..=y + fall {
+            if !self.fits(x, y) { return None; }
+            if self.stands(x, y) { return Some(y); }
+        }
+        None
+    }
+
+    /// Everywhere the walker can get to from `(x, y)` in one go, and what it costs.
+    fn moves(&mut self, (x, y): (i32, i32)) -> Vec<((i32, i32), u32)> {
+        let s = self.stride;
+        let mut moves = vec![];
+        for dir in [-1, 1] {
+            let nx = x + dir;
+            // along, down a step or off the edge
+            if let Some(ly) = self.land(nx, y, s.fall) {
+                moves.push(((nx, ly), 1 + (ly - y) as u32));
+            }
+            // up a step, walkers manage that without jumping
+            else if self.fits(x, y - 1) && self.stands(nx, y - 1) {
+                moves.push(((nx, y - 1), 2));
+            }
+            // over gaps and up onto ledges
+            for up in 1..=s.jump {
+                if !self.fits(x, y - up) { break; }
+                for across in 1..=s.leap {
+                    let tx = x + dir * across;
+                    if !self.fits(tx, y - up) { break; }
+                    if let Some(ly) = self.land(tx, y - up, s.fall + up) {
+                        moves.push(((tx, ly), JUMP_COST + (up + across + ly - (y - up)) as u32));
+                    }
+                }
+            }
+        }
+        moves
+    }
+}
+
+impl Grid {
+    /// The way from `from` to `to` for a walker `stride` big, as the cells its feet go through
+    /// after `from`. `None` if there's no way there (or it takes too long to find) or the walker
+    /// isn't standing anywhere to start from, like halfway through a jump.
+    pub fn find_path(&self, from: (i32, i32), to: (i32, i32), stride: Stride) -> Option<Vec<(i32, i32)>> {
+        self.search(from, to, stride).filter(|path| path.last().copied().unwrap_or(from) == to)
+    }
+
+    /// Like `find_path`, but if it can't get all the way it's the way to wherever it can get
+    /// that's closest, for walkers that only need to head somewhere.
+    pub fn path_towards(&self, from: (i32, i32), to: (i32, i32), stride: Stride) -> Option<Vec<(i32, i32)>> {
+        self.search(from, to, stride)
+    }
+
+    fn search(&self, from: (i32, i32), to: (i32, i32), stride: Stride) -> Option<Vec<(i32, i32)>> {
+        let mut terrain = Terrain { grid: self, stride, cells: HashMap::new() };
+        if !terrain.stands(from.0, from.1) { return None; }
+
+        let guess = |(x, y): (i32, i32)| x.abs_diff(to.0) + y.abs_diff(to.1);
+        let mut open = BinaryHeap::from([Reverse((guess(from), 0, from))]);
+        let mut cost = HashMap::from([(from, 0)]);
+        let mut came_from = HashMap::new();
+        let mut best = from;
+        let mut searched = 0;
+        while let Some(Reverse((_, g, at))) = open.pop() {
+            // already got here a cheaper way
+            if g > cost[&at] { continue; }
+            if guess(at) < guess(best) { best = at; }
+            searched += 1;
+            if at == to || searched > MAX_SEARCH { break; }
+
+            for (next, step) in terrain.moves(at) {
+                let g = g + step;
+                if cost.get(&next).is_none_or(|&c| g < c) {
+                    cost.insert(next, g);
+                    came_from.insert(next, at);
+                    open.push(Reverse((g + guess(next), g, next)));
+                }
+            }
+        }
+
+        let mut path = vec![];
+        let mut at = best;
+        while at != from {
+            path.push(at);
+            at = came_from[&at];
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Tile, TileIndex};
+    use crate::{AIR, LAVA, STONE};
+
+    const STRIDE: Stride = Stride { w: 1, h: 1, jump: 2, leap: 2, fall: 6 };
+    /// Where the walker's feet are on the floor.
+    const FLOOR: i32 = 9;
+
+    /// A 12x12 grid with stone from the row under `FLOOR` down.
+    fn floor() -> Grid {
+        let mut grid = Grid::new(12, 12).unwrap();
+        for y in FLOOR as usize + 1..12 {
+            for x in 0..12 {
+                grid[(x, y)] = Tile::new(STONE);
+            }
+        }
+        grid
+    }
+
+    fn set(grid: &mut Grid, x: i32, y: i32, tile: TileIndex) {
+        grid[(x as usize, y as usize)] = Tile::new(tile);
+    }
+
+    fn path(grid: &Grid) -> Option<Vec<(i32, i32)>> {
+        grid.find_path((1, FLOOR), (9, FLOOR), STRIDE)
+    }
+
+    #[test]
+    fn walks() {
+        assert_eq!(path(&floor()), Some((2..=9).map(|x| (x, FLOOR)).collect()));
+    }
+
+    #[test]
+    fn steps_up() {
+        let mut grid = floor();
+        for x in 5..12 {
+            set(&mut grid, x, FLOOR, STONE);
+        }
+        let path = grid.find_path((1, FLOOR), (9, FLOOR - 1), STRIDE).unwrap();
+        assert!(path.contains(&(5, FLOOR - 1)), "{:?}", path);
+        assert_eq!(path.last(), Some(&(9, FLOOR - 1)));
+    }
+
+    #[test]
+    fn jumps_a_gap() {
+        let mut grid = floor();
+        for y in FLOOR + 1..12 {
+            set(&mut grid, 5, y, AIR);
+        }
+        let path = path(&grid).unwrap();
+        assert!(path.iter().all(|&(x, y)| x != 5 || y < FLOOR), "{:?}", path);
+        assert_eq!(path.last(), Some(&(9, FLOOR)));
+    }
+
+    #[test]
+    fn too_far() {
+        let mut grid = floor();
+        for x in 4..7 {
+            for y in FLOOR + 1..12 {
+                set(&mut grid, x, y, AIR);
+            }
+        }
+        assert_eq!(path(&grid), None);
+        let towards = grid.path_towards((1, FLOOR), (9, FLOOR), STRIDE).unwrap();
+        assert_eq!(towards.last(), Some(&(3, FLOOR)));
+    }
+
+    #[test]
+    fn goes_round_hazards() {
+        let mut grid = floor();
+        set(&mut grid, 5, FLOOR, LAVA);
+        let path = path(&grid).unwrap();
+        assert!(!path.contains(&(5, FLOOR)), "{:?}", path);
+        assert_eq!(path.last(), Some(&(9, FLOOR)));
+    }
+}
@@ -0,0 +1,277 @@
+//! Pattern rules, what every material does each tick.
+//!
+//! A rule is a small picture of the neighbourhood around a tile (the `input`, centred on the tile
+//! being updated) and a picture of what it should look like afterwards (the `output`). Both are
+//! rows of characters, any odd size, 3x3 being the usual. A material lists its rules in priority
+//! order and the first one that matches (and passes its `chance`) is applied.
+//!
+//! Input characters:
+//!
+//! | char | matches                                                                  |
+//! |------|--------------------------------------------------------------------------|
+//! | `.`  | anything                                                                 |
+//! | `@`  | the same material as the centre, the centre itself must be `@`           |
+//! | `_`  | anything that isn't solid                                                |
+//! | `#`  | anything solid                                                           |
+//! | `~`  | non solid and lighter than the centre, stuff sinks into these            |
+//! | `^`  | non solid and denser than the centre, stuff floats up into these         |
+//! | `*`  | anything flammable                                                       |
+//! | `:`  | a corner, see below                                                      |
+//! | a-z  | the material with that `TileId::symbol`                                  |
+//!
+//! Corners are the cells next to a diagonal move. Unless the material has `TileId::slip` set, at
+//! least one of the `:` cells in a rule has to be non solid for it to match, so things don't
+//! squeeze through diagonal cracks (see `Neighbour`).
+//!
+//! What the edge of the world matches depends on the grid's `Edge`. A wall is solid, it only
+//! matches `.` and `#`. The void is an empty cell with no density, it matches `.`, `_`, `~` and
+//! corners. Anything written into either is lost.
+//!
+//! Output characters:
+//!
+//! | char | becomes                                                                  |
+//! |------|--------------------------------------------------------------------------|
+//! | `.`  | left alone                                                               |
+//! | `@`  | the centre tile, moved here (keeps its age and the rest)                 |
+//! | `%`  | whatever was in the cell the `@` moved to, so `@` and `%` make a swap     |
+//! | a-z  | a brand new tile of that material                                        |
+use super::{GridResult, Result, Sample, TileIndex, TILES};
+
+/// Which other orientations of a rule are tried as well. When there are several they're tried
+/// starting from a random one, so nothing leans to one side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symmetry {
+    None,
+    /// The rule and its left-right mirror image.
+    MirrorX,
+    /// All four quarter turns of the rule.
+    Rotations,
+}
+
+/// Turns the offset of a cell of a rule into where it is in one orientation of the rule.
+pub(super) type Orient = fn(isize, isize) -> (isize, isize);
+
+#[derive(Debug)]
+pub struct Rule {
+    pub input   : &'static [&'static str],
+    pub output  : &'static [&'static str],
+    /// Odds of the rule even being looked at in a given tick.
+    pub chance  : f32,
+    pub symmetry: Symmetry,
+}
+
+impl Rule {
+    pub const fn new(input: &'static [&'static str], output: &'static [&'static str]) -> Self {
+        Self { input, output, chance: 1.0, symmetry: Symmetry::None }
+    }
+
+    pub const fn chance(self, chance: f32) -> Self {
+        Self { chance, ..self }
+    }
+
+    pub const fn mirrored(self) -> Self {
+        Self { symmetry: Symmetry::MirrorX, ..self }
+    }
+
+    pub const fn rotated(self) -> Self {
+        Self { symmetry: Symmetry::Rotations, ..self }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum In {
+    Any,
+    Me,
+    Empty,
+    Solid,
+    Lighter,
+    Denser,
+    Flammable,
+    Corner,
+    Is(TileIndex),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Out {
+    Keep,
+    Centre,
+    Displaced,
+    Set(TileIndex),
+}
+
+/// A cell of a compiled rule, the offset is relative to the centre.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Cell {
+    pub dx  : isize,
+    pub dy  : isize,
+    pub input : In,
+    pub output: Out,
+}
+
+/// A `Rule` parsed into something quicker to match against.
+#[derive(Clone, Debug)]
+pub(super) struct CompiledRule {
+    pub cells   : Vec<Cell>,
+    pub chance  : f32,
+    pub symmetry: Symmetry,
+}
+
+pub(super) fn symbol_index(c: char) -> Option<TileIndex> {
+    TILES.iter().position(|t| t.symbol == c)
+}
+
+impl CompiledRule {
+    pub fn compile(material: &str, rule: &Rule) -> Result<Self> {
+        let bad = |reason: String| GridResult::BadRule(format!("{}: {}", material, reason));
+
+        let h = rule.input.len();
+        let w = rule.input.first().map(|r| r.chars().count()).unwrap_or(0);
+        if h.is_multiple_of(2) || w.is_multiple_of(2) {
+            return Err(bad(format!("patterns must have an odd size, got {}x{}", w, h)));
+        }
+        if rule.output.len() != h
+            || rule.input.iter().chain(rule.output.iter()).any(|r| r.chars().count() != w)
+        {
+            return Err(bad("input and output must be the same size".into()));
+        }
+
+        let mut cells = vec![];
+        let mut moves = 0;
+        let mut displaced = false;
+        for (y, (irow, orow)) in rule.input.iter().zip(rule.output.iter()).enumerate() {
+            for (x, (i, o)) in irow.chars().zip(orow.chars()).enumerate() {
+                let (dx, dy) = (x as isize - w as isize / 2, y as isize - h as isize / 2);
+                let input = match i {
+                    '.' => In::Any,
+                    '@' => In::Me,
+                    '_' => In::Empty,
+                    '#' => In::Solid,
+                    '~' => In::Lighter,
+                    '^' => In::Denser,
+                    '*' => In::Flammable,
+                    ':' => In::Corner,
+                    c => In::Is(symbol_index(c).ok_or_else(|| bad(format!("unknown input '{}'", c)))?),
+                };
+                let output = match o {
+                    '.' => Out::Keep,
+                    '@' => { moves += 1; Out::Centre }
+                    '%' => { displaced = true; Out::Displaced }
+                    c => Out::Set(symbol_index(c).ok_or_else(|| bad(format!("unknown output '{}'", c)))?),
+                };
+                if dx == 0 && dy == 0 && input != In::Me {
+                    return Err(bad("the centre of the input must be '@'".into()));
+                }
+                if input != In::Any || output != Out::Keep {
+                    cells.push(Cell { dx, dy, input, output });
+                }
+            }
+        }
+        if moves > 1 {
+            return Err(bad("the centre tile can only go to one place".into()));
+        }
+        if displaced && moves == 0 {
+            return Err(bad("'%' needs an '@' in the output".into()));
+        }
+
+        Ok(Self { cells, chance: rule.chance, symmetry: rule.symmetry })
+    }
+
+    /// The offsets of every orientation this rule is tried in, as functions of the original ones.
+    pub fn orientations(&self) -> &'static [Orient] {
+        match self.symmetry {
+            Symmetry::None => &[|x, y| (x, y)],
+            Symmetry::MirrorX => &[|x, y| (x, y), |x, y| (-x, y)],
+            Symmetry::Rotations => &[|x, y| (x, y), |x, y| (-y, x), |x, y| (-x, -y), |x, y| (y, -x)],
+        }
+    }
+}
+
+/// Compiles one set of rules (picked by `rules`) of every material, indexed the same way as
+/// `TILES`.
+pub(super) fn compile_all(rules: fn(&crate::TileId) -> &'static [Rule]) -> Result<Vec<Vec<CompiledRule>>> {
+    TILES.iter()
+        .map(|t| rules(t).iter().map(|r| CompiledRule::compile(t.name, r)).collect())
+        .collect()
+}
+
+/// Whether `sample` fits a single input cell, for a rule running on a tile of material `me`.
+pub(super) fn matches(input: In, sample: Sample, me: TileIndex) -> bool {
+    let centre = &TILES[me];
+    let tile = match sample {
+        Sample::Tile(tile) => tile,
+        Sample::Wall => return matches!(input, In::Any | In::Solid | In::Corner),
+        Sample::Void => return matches!(input, In::Any | In::Empty | In::Lighter | In::Corner),
+    };
+    let id = &TILES[tile.index];
+    match input {
+        In::Any | In::Corner => true,
+        In::Me => tile.index == me,
+        In::Empty => !id.solid,
+        In::Solid => id.solid,
+        In::Lighter => !id.solid && id.density < centre.density,
+        In::Denser => !id.solid && id.density > centre.density,
+        In::Flammable => id.flammable,
+        In::Is(index) => tile.index == index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Tile;
+    use crate::{AIR, SAND, STONE};
+
+    /// What's wrong with a rule made of `input` and `output`, `None` if nothing is.
+    fn error(input: &'static [&'static str], output: &'static [&'static str]) -> Option<String> {
+        CompiledRule::compile("test", &Rule::new(input, output)).err().map(|e| e.to_string())
+    }
+
+    #[test]
+    fn compile_errors() {
+        let cases: &[(&'static [&'static str], &'static [&'static str], &str)] = &[
+            (&["..", ".@"], &["..", ".."], "odd size"),
+            (&["...", ".@."], &["...", "..."], "odd size"),
+            (&["...", ".@.", ".."], &["...", "...", "..."], "same size"),
+            (&["...", ".@.", "..."], &["...", "...", "...."], "same size"),
+            (&["...", ".@.", "..."], &["...", "..."], "same size"),
+            (&["...", ".@Q", "..."], &["...", "...", "..."], "unknown input 'Q'"),
+            (&["...", ".@.", "..."], &["...", "..Q", "..."], "unknown output 'Q'"),
+            (&["...", ".s.", "..."], &["...", "...", "..."], "centre"),
+            (&["...", ".@.", "..."], &["@..", "...", "..@"], "one place"),
+            (&["...", ".@.", "..."], &["...", ".%.", "..."], "'%'"),
+        ];
+        for (input, output, why) in cases {
+            let e = error(input, output);
+            assert!(e.as_ref().is_some_and(|e| e.contains(why)), "{:?} -> {:?}: {:?}", input, output, e);
+        }
+        assert_eq!(error(&["...", ".@.", ".~."], &["...", ".%.", ".@."]), None);
+        assert_eq!(error(&["@"], &["s"]), None);
+    }
+
+    /// Where a rule's `(dx, dy)` cell ends up in each of its orientations.
+    fn images(rule: Rule, dx: isize, dy: isize) -> Vec<(isize, isize)> {
+        let rule = CompiledRule::compile("test", &rule).unwrap();
+        let mut images: Vec<_> = rule.orientations().iter().map(|o| o(dx, dy)).collect();
+        images.sort();
+        images
+    }
+
+    #[test]
+    fn symmetry() {
+        let rule = || Rule::new(&["...", ".@.", "..."], &["...", "...", "..."]);
+        assert_eq!(images(rule(), 1, 1), vec![(1, 1)]);
+        assert_eq!(images(rule().mirrored(), 1, 1), vec![(-1, 1), (1, 1)]);
+        assert_eq!(images(rule().rotated(), 0, 1), vec![(-1, 0), (0, -1), (0, 1), (1, 0)]);
+        assert_eq!(images(rule().rotated(), 1, 1), vec![(-1, -1), (-1, 1), (1, -1), (1, 1)]);
+    }
+
+    #[test]
+    fn edges() {
+        let inputs = [In::Any, In::Empty, In::Solid, In::Lighter, In::Denser, In::Corner, In::Is(AIR)];
+        let fits = |sample| inputs.map(|input| matches(input, sample, SAND));
+        assert_eq!(fits(Sample::Wall), [true, false, true, false, false, true, false]);
+        assert_eq!(fits(Sample::Void), [true, true, false, true, false, true, false]);
+        assert_eq!(fits(Sample::Tile(Tile::new(AIR))), [true, true, false, true, false, true, true]);
+        assert_eq!(fits(Sample::Tile(Tile::new(STONE))), [true, false, true, false, false, true, false]);
+    }
+}
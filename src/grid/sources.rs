@@ -0,0 +1,33 @@
+//! Tiles that make or delete other tiles, taps and volcanoes pour out their material and the void
+//! eats whatever touches it.
+use super::neighbour::{Neighbour, NOSLIP_NEIGHBOURS};
+use super::{Grid, Tile};
+use crate::AIR;
+
+/// Where emitters pour their material out, they don't bother with the diagonals.
+const EMIT_NEIGHBOURS: [Neighbour; 4] = [Neighbour::Down, Neighbour::Left, Neighbour::Right, Neighbour::Up];
+
+impl Grid {
+    pub(super) fn update_source(&mut self, x: usize, y: usize, tile_id: &crate::TileId) {
+        if let Some((material, rate)) = tile_id.emits {
+            for n in EMIT_NEIGHBOURS {
+                let Ok(p) = n.check_free(self, x, y) else { continue; };
+                if self[p].index == AIR && self.rng.chance(rate) {
+                    self[p] = Tile::new(material);
+                    self[p].updated = true;
+                }
+            }
+        }
+
+        if tile_id.sink {
+            for n in NOSLIP_NEIGHBOURS.iter().filter(|n| **n != Neighbour::Ident) {
+                let (dx, dy) = n.offset();
+                let Some(p) = self.resolve(x as isize + dx, y as isize + dy) else { continue; };
+                // two voids next to each other would just eat each other
+                if !crate::TILES[self[p].index].sink {
+                    self[p] = Tile::default();
+                }
+            }
+        }
+    }
+}
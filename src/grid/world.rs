@@ -0,0 +1,45 @@
+//! Writing the tiles of a world down as text and reading them back, a row of `TileId::symbol`s
+//! per grid row. Only which material is where is kept, not how old the tiles are or what charge
+//! they carry.
+use super::{rule::symbol_index, Grid, GridResult, Result, Tile, TILES};
+
+impl Grid {
+    /// The grid as rows of material symbols, top to bottom.
+    pub fn to_rows(&self) -> Vec<String> {
+        let (w, h) = self.get_wh();
+        (0..h)
+            .map(|y| (0..w).map(|x| TILES[self[(x, y)].index].symbol).collect())
+            .collect()
+    }
+
+    /// Clears the grid and fills it from rows like the ones `to_rows` makes, which have to be
+    /// exactly the size of the grid.
+    pub fn load_rows<S: AsRef<str>>(&mut self, rows: &[S]) -> Result<()> {
+        let (w, h) = self.get_wh();
+        if rows.len() != h || rows.iter().any(|r| r.as_ref().chars().count() != w) {
+            return Err(GridResult::BadWorld(format!("expected {} rows of {} tiles", h, w)));
+        }
+        let mut tiles = Vec::with_capacity(w * h);
+        for row in rows {
+            for c in row.as_ref().chars() {
+                tiles.push(Tile::new(symbol_index(c).ok_or_else(|| GridResult::BadWorld(format!("unknown material '{}'", c)))?));
+            }
+        }
+        self.clear();
+        self.grid = tiles;
+        Ok(())
+    }
+
+    /// A hash of every tile, to tell whether two runs ended up with exactly the same world.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for tile in &self.grid {
+            for byte in (tile.index as u32).to_le_bytes().into_iter().chain(tile.age.to_le_bytes()).chain([tile.state, tile.charge]) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100_0000_01b3);
+            }
+        }
+        hash
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::grid::TileIndex;
+use crate::TILES;
+
+/// Tiles the player has dug up and is carrying around, counted per material.
+#[derive(Default, Debug)]
+pub struct Inventory {
+    items: BTreeMap<TileIndex, u32>,
+    /// What gets placed next, always something there's at least one of.
+    selected: Option<TileIndex>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, index: TileIndex) {
+        *self.items.entry(index).or_insert(0) += 1;
+        if self.selected.is_none() {
+            self.selected = Some(index);
+        }
+    }
+
+    /// Takes one of the selected material out, moving the selection on if that was the last.
+    pub fn take_selected(&mut self) -> Option<TileIndex> {
+        let index = self.selected?;
+        let count = self.items.get_mut(&index)?;
+        *count -= 1;
+        if *count == 0 {
+            self.items.remove(&index);
+            self.selected = self.items.range(index..).chain(self.items.iter()).map(|(i, _)| *i).next();
+        }
+        Some(index)
+    }
+
+    pub fn select_next(&mut self) {
+        let Some(cur) = self.selected else { return; };
+        self.selected = self.items.range(cur + 1..).chain(self.items.iter()).map(|(i, _)| *i).next();
+    }
+
+    pub fn select_prev(&mut self) {
+        let Some(cur) = self.selected else { return; };
+        self.selected = self.items.range(..cur).rev().chain(self.items.iter().rev()).map(|(i, _)| *i).next();
+    }
+}
+
+/// Everything in the inventory by name, the selected material in brackets.
+impl fmt::Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.items.is_empty() {
+            return write!(f, "Nothing");
+        }
+        let items: Vec<_> = self.items.iter()
+            .map(|(i, n)| match self.selected == Some(*i) {
+                true => format!("[{} {}]", TILES[*i].name, n),
+                false => format!("{} {}", TILES[*i].name, n),
+            })
+            .collect();
+        write!(f, "{}", items.join(", "))
+    }
+}
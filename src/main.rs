@@ -1,129 +1,450 @@
 use sdl2::pixels::Color;
 use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
 use sdl2::event::WindowEvent;
-use sdl2::keyboard::Keycode;
 use sdl2::render::TextureQuery;
 use sdl2::rect::Rect;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 mod grid;
-use grid::{Grid, TILE_HEIGHT, TILE_WIDTH, neighbour::*, TileIdType};
+use grid::{boundary, Grid, TILE_HEIGHT, TILE_WIDTH, rule::Rule, TileIndex};
+mod rng;
 mod vec2;
 use vec2::*;
 mod player;
 use player::*;
+mod entity;
+use entity::{everyone, Body, Entity};
+mod creature;
+use creature::{Creature, Temper};
+mod projectile;
+use projectile::{Payload, Projectile};
+mod inventory;
+use inventory::Inventory;
+mod controller;
+use controller::{Actions, PlayerController};
+mod bindings;
+use bindings::{Action, Bindings, Input, Seat, CONTROLS_FILE};
+mod gamepad;
+use gamepad::Gamepads;
+mod replay;
+use replay::{Frame, Held, Press, Recorder, Replay, HELD_ACTIONS};
+use rng::Rng;
+mod camera;
+use camera::Camera;
+mod net;
+use net::{Client, Server};
 
 const WINDOW_WIDTH      : usize = 800;
 const WINDOW_HEIGHT     : usize = 600;
 
 const CURSOR_COLOUR     : Color = Color::RGB(200, 200, 200);
 const MAX_CURSOR_SIZE   : usize = 10;
+/// How fast the right stick moves the cursor when it's pushed all the way, in tiles a second.
+const CURSOR_STICK_SPEED: f32   = 40.0;
+/// Room between where the players start out, in pixels.
+const SPAWN_GAP         : f32   = TILE_WIDTH as f32 * 4.0;
 
 const TEXT_COLOUR       : Color = Color::RGBA(255, 255, 255, 255);
 const DEBUG_DRAW_COLOUR : Color = Color::RGBA(255, 0, 0, 255);
 const DEFAULT_FONT      : &str  = "/usr/share/fonts/truetype/lato/Lato-Medium.ttf";
+const HELP_BACKGROUND   : Color = Color::RGB(10, 10, 30);
+/// Gap around the help overlay, in pixels.
+const HELP_MARGIN       : i32   = 20;
 
 
-/// Stupid but this is how many frames must pass for the simulation to tick. So 2 means after 2
-/// frames the simulation updates.
-const SIMULATION_FRAME_DELAY    : usize  = 2;
+/// Frames drawn per second, at most.
 const FPS                       : u32    = 60;
+/// Time between grid ticks.
+const GRID_STEP                 : Duration = Duration::from_nanos(1_000_000_000 / 30);
+/// Time between player physics ticks, the controls and the constants in `player` are tuned for
+/// 30 a second.
+const PLAYER_STEP               : Duration = Duration::from_nanos(1_000_000_000 / 30);
+/// Most time the simulation catches up on in one frame, so a long hitch (or a breakpoint) doesn't
+/// make it spiral trying to run every tick it missed.
+const MAX_CATCH_UP              : Duration = Duration::from_millis(250);
 
 #[derive(Debug)]
 struct TileId {
     name: &'static str,
+    /// Stands for this material in rule patterns, see `grid::rule`.
+    symbol      : char,
     // u32 > 0xRR_GG_BB_AA
     colour      : (u8, u8, u8),
-    gravity     : bool,
     flammable   : bool,
     solid       : bool,
-// FIXME: make weight a substitute for solidity
-    sort        : TileIdType,
-    neighbours  : &'static [Neighbour],
+    /// What the tile does every tick, in priority order.
+    rules       : &'static [Rule],
+    /// Whether sparks travel through it, see `grid::electricity`.
+    conductive  : bool,
+    /// What the tile does on the ticks it carries a spark, on top of its `rules`.
+    powered     : &'static [Rule],
+    /// Material it pours into the free cells around it, and the odds of doing so per cell per
+    /// tick.
+    emits       : Option<(TileIndex, f32)>,
+    /// Deletes anything that touches it.
+    sink        : bool,
+    /// How much force it takes to blow it away, see `grid::explosion`.
+    hardness    : f32,
+    /// Needs something holding it up when structural integrity is on, see `grid::integrity`.
+    structural  : bool,
+    /// What it crumbles into when it's left hanging, it falls in one piece if `None`.
+    breaks_into : Option<TileIndex>,
+    /// Player ticks it takes to dig out, it can't be dug at all if `None`. Only solid tiles are
+    /// dug.
+    dig_time    : Option<u32>,
+    /// Blows up with this power when it touches fire, lava or a spark.
+    explosive   : Option<f32>,
+    /// Whether diagonal moves slip through diagonal cracks or get stuck on the corners, see the
+    /// corners in `grid::rule`.
+    slip        : bool,
+    /// Used for buoyancy, lighter things rise through heavier non-solid things.
+    density     : f32,
+    /// How much it drags on the player moving through it, the fraction of their speed lost per
+    /// tick when they're all the way in it. Only matters for non-solid stuff.
+    viscosity   : f32,
+    /// Health the player loses per tick when they're all the way in it.
+    damage      : f32,
+    /// How many ticks the tile lives for before it turns into `decays_into`, forever if `None`.
+    lifetime    : Option<u32>,
+    decays_into : TileIndex,
 }
 
 impl TileId {
     const fn default() -> Self {
         Self {
             name: "!ERROR!", 
+            symbol: '!',
             colour: (255, 0, 0),
-            gravity: false,
             flammable: false,
             solid: true,
-            sort: TileIdType::Static,
-            neighbours: &[],
+            rules: &[],
+            conductive: false,
+            powered: &[],
+            emits: None,
+            sink: false,
+            hardness: 2.0,
+            structural: false,
+            breaks_into: None,
+            dig_time: Some(20),
+            explosive: None,
+            slip: false,
+            density: 2000.0,
+            viscosity: 0.0,
+            damage: 0.0,
+            lifetime: None,
+            decays_into: AIR,
         }
     }
 }
 
-
+const AIR   : TileIndex = 0;
+const STONE : TileIndex = 2;
+const SAND  : TileIndex = 3;
+const GRAVEL: TileIndex = 4;
+const SMOKE : TileIndex = 5;
+const WATER : TileIndex = 6;
+const FIRE  : TileIndex = 8;
+const CELL  : TileIndex = 9;
+const DYING : TileIndex = 10;
+const COPPER: TileIndex = 11;
+const HEAD  : TileIndex = 12;
+const TAIL  : TileIndex = 13;
+const ANT   : TileIndex = 14;
+const BATTERY: TileIndex = 16;
+const LAVA  : TileIndex = 18;
+
+/// Power of the explosion the middle mouse button sets off.
+const DETONATOR_POWER: f32 = 5.0;
+
+/// Falls straight down into anything lighter.
+const FALL  : Rule = Rule::new(&["...", ".@.", ".~."], &["...", ".%.", ".@."]);
+/// Slides down either diagonal.
+const SLIDE : Rule = Rule::new(&["...", ":@.", "~:."], &["...", ".%.", "@.."]).mirrored();
+/// Spreads out sideways, like a liquid.
+const FLOW  : Rule = Rule::new(&["...", "~@.", "..."], &["...", "@%.", "..."]).mirrored();
+/// Floats straight up through anything denser.
+const RISE  : Rule = Rule::new(&[".^.", ".@.", "..."], &[".@.", ".%.", "..."]);
+/// Floats up either diagonal.
+const FLOAT : Rule = Rule::new(&["^:.", ":@.", "..."], &["@..", ".%.", "..."]).mirrored();
+/// Wanders around in the air, like a gas.
+const DRIFT : Rule = Rule::new(&["...", "a@.", "..."], &["...", "@%.", "..."]).mirrored().chance(0.4);
+/// Sinks back down through the air now and then, so a gas spreads out instead of all sitting on
+/// the ceiling.
+const SETTLE: Rule = Rule::new(&["...", ".@.", ".a."], &["...", ".%.", ".@."]).chance(0.1);
 
 const AIR_TILE: TileId = TileId {
     name        : "Air",
+    symbol      : 'a',
     colour      : (24, 24, 24),
-    gravity     : false,
     flammable   : false,
     solid       : false,
-    sort        : TileIdType::Static,
-    neighbours  : &[],
+    rules       : &[],
+    conductive  : false,
+    powered     : &[],
+    emits       : None,
+    sink        : false,
+    hardness    : 0.0,
+    structural  : false,
+    breaks_into : None,
+    dig_time    : None,
+    explosive   : None,
+    slip        : false,
+    density     : 1.2,
+    viscosity   : 0.0,
+    damage      : 0.0,
+    lifetime    : None,
+    decays_into : AIR,
 };
 
-const TILES: &[TileId] = { 
-    use Neighbour::*;
-    &[
-        AIR_TILE,
-        TileId { 
-            name: "Wood",
-            colour: (164, 42, 42),
-            flammable: true,
-            ..TileId::default()
-        },
-        TileId {
-            name: "Stone", 
-            colour: (180, 170, 180),
-            ..TileId::default()
-        },
-        TileId {
-            name: "Sand", 
-            colour: (255, 255, 0),
-            gravity: true,
-            sort: TileIdType::Dynamic,
-            neighbours: &[Down, DownLeft, DownRight],
-            ..TileId::default()
-        },
-        TileId {
-            name: "Gravel", 
-            colour: (90, 89, 88),
-            gravity: true,
-            sort: TileIdType::Static,
-            neighbours: &[Down, DownLeft, DownRight],
-            ..TileId::default()
-        },
-        TileId {
-            name: "Smoke", 
-            colour: (244, 234, 250),
-            gravity: true,
-            solid: false,
-            sort: TileIdType::Dynamic,
-            neighbours: &[Up, UpLeft, UpRight, Left, Right],
-            ..TileId::default()
-        },
-        TileId {
-            name: "Water",
-            colour: (0, 0, 255),
-            gravity: true,
-            solid: false,
-            neighbours: &[Down, DownLeft, DownRight, Left, Right],
-            ..TileId::default()
-        }
-    ]
-};
+const TILES: &[TileId] = &[
+    AIR_TILE,
+    TileId { 
+        name: "Wood",
+        symbol: 'l',
+        colour: (164, 42, 42),
+        flammable: true,
+        hardness: 1.5,
+        structural: true,
+        dig_time: Some(15),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Stone", 
+        symbol: 's',
+        colour: (180, 170, 180),
+        hardness: 4.0,
+        structural: true,
+        breaks_into: Some(GRAVEL),
+        dig_time: Some(40),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Sand", 
+        symbol: 'n',
+        colour: (255, 255, 0),
+        rules: &[FALL, SLIDE],
+        slip: true,
+        density: 1600.0,
+        hardness: 0.5,
+        dig_time: Some(5),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Gravel", 
+        symbol: 'g',
+        colour: (90, 89, 88),
+        rules: &[FALL, SLIDE],
+        density: 1800.0,
+        hardness: 1.0,
+        dig_time: Some(10),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Smoke", 
+        symbol: 'k',
+        colour: (244, 234, 250),
+        solid: false,
+        rules: &[RISE, FLOAT, DRIFT, SETTLE],
+        slip: true,
+        density: 0.6,
+        viscosity: 0.05,
+        lifetime: Some(300),
+        decays_into: AIR,
+        hardness: 0.0,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Water",
+        symbol: 'w',
+        colour: (0, 0, 255),
+        solid: false,
+        rules: &[FALL, SLIDE, FLOW],
+        slip: true,
+        density: 1000.0,
+        viscosity: 0.3,
+        hardness: 0.5,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Steam",
+        symbol: 'v',
+        colour: (200, 210, 230),
+        solid: false,
+        rules: &[RISE, FLOAT, DRIFT, SETTLE],
+        slip: true,
+        density: 0.5,
+        viscosity: 0.05,
+        lifetime: Some(200),
+        decays_into: WATER,
+        hardness: 0.0,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Fire",
+        symbol: 'f',
+        colour: (255, 120, 20),
+        solid: false,
+        rules: &[
+            // water puts it out and boils
+            Rule::new(&["...", ".@w", "..."], &["...", ".av", "..."]).rotated(),
+            Rule::new(&["...", ".@*", "..."], &["...", "..f", "..."]).rotated().chance(0.1),
+            RISE.chance(0.2),
+        ],
+        density: 0.3,
+        lifetime: Some(40),
+        decays_into: SMOKE,
+        hardness: 0.0,
+        damage: 4.0,
+        ..TileId::default()
+    },
+    // the rest only do anything in the automaton modes, see `grid::automaton`
+    TileId {
+        name: "Cell",
+        symbol: 'c',
+        colour: (230, 230, 230),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Dying",
+        symbol: 'y',
+        colour: (60, 90, 200),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Copper",
+        symbol: 'u',
+        colour: (200, 120, 40),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Head",
+        symbol: 'e',
+        colour: (80, 160, 255),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Tail",
+        symbol: 't',
+        colour: (255, 80, 60),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Ant",
+        symbol: 'x',
+        colour: (200, 30, 200),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Metal",
+        symbol: 'm',
+        colour: (120, 130, 150),
+        conductive: true,
+        density: 7800.0,
+        hardness: 6.0,
+        dig_time: Some(60),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Battery",
+        symbol: 'b',
+        colour: (40, 160, 60),
+        conductive: true,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Heater",
+        symbol: 'h',
+        colour: (150, 60, 40),
+        conductive: true,
+        powered: &[
+            Rule::new(&["...", ".@w", "..."], &["...", "..v", "..."]).rotated(),
+            Rule::new(&["...", ".@*", "..."], &["...", "..f", "..."]).rotated(),
+        ],
+        ..TileId::default()
+    },
+    TileId {
+        name: "Lava",
+        symbol: 'r',
+        colour: (230, 70, 10),
+        solid: false,
+        rules: &[
+            // cools into stone in water, boiling it
+            Rule::new(&["...", ".@w", "..."], &["...", ".sv", "..."]).rotated(),
+            Rule::new(&["...", ".@*", "..."], &["...", "..f", "..."]).rotated().chance(0.2),
+            FALL,
+            SLIDE,
+            FLOW.chance(0.2),
+        ],
+        slip: true,
+        density: 3100.0,
+        viscosity: 0.75,
+        hardness: 0.5,
+        damage: 10.0,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Tap",
+        symbol: 'p',
+        colour: (150, 150, 220),
+        emits: Some((WATER, 0.2)),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Volcano",
+        symbol: 'o',
+        colour: (90, 30, 20),
+        emits: Some((LAVA, 0.05)),
+        hardness: f32::INFINITY,
+        dig_time: None,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Explosive",
+        symbol: 'i',
+        colour: (200, 20, 60),
+        hardness: 0.5,
+        explosive: Some(6.0),
+        ..TileId::default()
+    },
+    TileId {
+        name: "Void",
+        symbol: 'z',
+        colour: (0, 0, 0),
+        sink: true,
+        hardness: f32::INFINITY,
+        dig_time: None,
+        ..TileId::default()
+    },
+    TileId {
+        name: "Acid",
+        symbol: 'd',
+        colour: (130, 230, 40),
+        solid: false,
+        rules: &[
+            // eats through most things, using itself up
+            Rule::new(&["...", ".@.", ".s."], &["...", ".a.", ".a."]).rotated().chance(0.05),
+            Rule::new(&["...", ".@.", ".l."], &["...", ".a.", ".a."]).rotated().chance(0.1),
+            Rule::new(&["...", ".@.", ".n."], &["...", ".a.", ".a."]).rotated().chance(0.1),
+            Rule::new(&["...", ".@.", ".g."], &["...", ".a.", ".a."]).rotated().chance(0.1),
+            Rule::new(&["...", ".@.", ".m."], &["...", ".a.", ".a."]).rotated().chance(0.02),
+            FALL,
+            SLIDE,
+            FLOW,
+        ],
+        slip: true,
+        density: 1100.0,
+        viscosity: 0.3,
+        damage: 3.0,
+        hardness: 0.5,
+        ..TileId::default()
+    },
+];
 
 pub struct Canvas2{
     pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
     pub w: usize, pub h: usize,
+    pub camera: Camera,
 }
 
 impl Canvas2 {
@@ -140,13 +461,19 @@ impl Canvas2 {
     }
 
     fn scale_rect(&self, rect: Rect) -> Rect {
-        Rect::new(
-            ((rect.x as f64 / WINDOW_WIDTH as f64) * self.w as f64) as i32, 
-            ((rect.y as f64 / WINDOW_HEIGHT as f64) * self.h as f64) as i32,
-            // rect.width(), rect.height()
-            ((rect.w as f64 / WINDOW_WIDTH as f64) * self.w as f64) as u32,
-            ((rect.h as f64 / WINDOW_HEIGHT as f64) * self.h as f64) as u32,
-        )
+        // both corners go through the camera, so tiles next to each other still meet when zoomed
+        let (sx, sy) = (self.w as f32 / WINDOW_WIDTH as f32, self.h as f32 / WINDOW_HEIGHT as f32);
+        let tl = self.camera.to_screen(Vec2(rect.x as f32, rect.y as f32));
+        let br = self.camera.to_screen(Vec2((rect.x + rect.w) as f32, (rect.y + rect.h) as f32));
+        let (x0, y0) = ((tl.0 * sx).round() as i32, (tl.1 * sy).round() as i32);
+        let (x1, y1) = ((br.0 * sx).round() as i32, (br.1 * sy).round() as i32);
+        Rect::new(x0, y0, (x1 - x0).max(0) as u32, (y1 - y0).max(0) as u32)
+    }
+
+    /// The world pixel under window pixel `(x, y)`.
+    pub fn to_world(&self, x: i32, y: i32) -> Vec2 {
+        let p = Vec2(x as f32 / self.w as f32 * WINDOW_WIDTH as f32, y as f32 / self.h as f32 * WINDOW_HEIGHT as f32);
+        self.camera.to_world(p)
     }
 
     pub fn get_rel_wh(&self) -> (usize, usize) {
@@ -170,7 +497,7 @@ impl Canvas2 {
 fn draw_cursor(mut x: usize, mut y: usize, canvas: &mut Canvas2, size: usize) {
     // let rect = Rect::new(x as i32 / TILE_WIDTH as i32 * TILE_WIDTH as i32, y as i32 % TILE_HEIGHT as i32, TILE_WIDTH as u32, TILE_WIDTH as u32);
     let rect = if size == 1 {
-        Rect::new(x as i32 / TILE_WIDTH as i32, y as i32 / TILE_HEIGHT as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32)
+        Rect::new(x as i32 * TILE_WIDTH as i32, y as i32 * TILE_HEIGHT as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32)
     } else {
         if x.checked_sub(size/2).is_none() { x = size/2; }
         if y.checked_sub(size/2).is_none() { y = size/2; }
@@ -180,6 +507,36 @@ fn draw_cursor(mut x: usize, mut y: usize, canvas: &mut Canvas2, size: usize) {
     let _ = canvas.fill_rect(rect);
 }
 
+/// Where player `player` starts, the first in the middle of the world and the rest either side
+/// of them in turn.
+fn spawn_point(player: usize) -> Vec2 {
+    let side = if player % 2 == 1 { 1.0 } else { -1.0 };
+    let offset = player.div_ceil(2) as f32 * side * SPAWN_GAP;
+    Vec2(WINDOW_WIDTH as f32 / 2.0 + 5.0 + offset, WINDOW_HEIGHT as f32 / 2.0)
+}
+
+/// What the main loop keeps for each player on top of the `Player`.
+#[derive(Default)]
+struct Slot {
+    controller: PlayerController,
+    /// Presses and clicks since the last player tick, so quick ones aren't missed between ticks.
+    pending: Actions,
+    /// Nobody here drives players that joined over the network or turned up in a replay.
+    seat: Option<Seat>,
+    /// Where the player's cursor is in tiles, kept in between so a stick can nudge it slowly.
+    cursor: Vec2,
+}
+
+/// Adds players nobody here is driving until there are `count` of them.
+fn join(players: &mut Vec<Player>, slots: &mut Vec<Slot>, count: usize) {
+    while players.len() < count.min(PLAYER_COLOURS.len()) {
+        let spawn = spawn_point(players.len());
+        players.push(Player::new(spawn.0, spawn.1, PLAYER_COLOURS[players.len()]));
+        // starting out on the player, where it's in reach
+        slots.push(Slot { cursor: Vec2(spawn.0 / TILE_WIDTH as f32, spawn.1 / TILE_HEIGHT as f32), ..Default::default() });
+    }
+}
+
 fn texture_and_rect_from_str<'a>(ttf_ctx: &'a sdl2::ttf::Sdl2TtfContext, texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>, text: &str, font: &str, font_size: u16, colour: Color) -> (sdl2::render::Texture<'a>, Rect) {
     let mut font = ttf_ctx.load_font(font, font_size).unwrap();
     font.set_style(sdl2::ttf::FontStyle::BOLD);
@@ -200,6 +557,7 @@ fn texture_and_rect_from_str<'a>(ttf_ctx: &'a sdl2::ttf::Sdl2TtfContext, texture
 pub fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
+    let mut gamepads = Gamepads::new(sdl_context.game_controller()?);
     let ttf_ctx = sdl2::ttf::init().map_err(|e| e.to_string())?;
 
     let window = video_subsystem.window(":(", WINDOW_WIDTH as u32, WINDOW_HEIGHT as u32)
@@ -210,28 +568,74 @@ pub fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let (w, h) = (window.size().0 as usize, window.size().1 as usize);
 
     let canvas = window.into_canvas().build()?;
-    let mut canvas = Canvas2 { canvas, w, h };
+    let mut canvas = Canvas2 { canvas, w, h, camera: Camera::default() };
 
     let texture_creator = canvas.inner().texture_creator();
 
     // let (texture, target) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, "hello world", DEFAULT_FONT, 24, TEXT_COLOUR);
         
+    // `--record FILE` writes this run down, `--replay FILE` plays one back, `--players N` for
+    // local co-op, `--host ADDRESS` lets others `--join ADDRESS` over the network
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+    let mut replay = arg("--replay").map(|path| Replay::load(path)).transpose()?;
+    let mut server = arg("--host").map(|addr| Server::bind(addr)).transpose()?;
+    let mut client = arg("--join").map(|addr| Client::connect(addr)).transpose()?;
+    let player_count = match (&replay, arg("--players")) {
+        (Some(replay), _) => replay.players,
+        (None, Some(n)) => n.parse().map_err(|_| format!("bad number of players '{}'", n))?,
+        (None, None) => 1,
+    }.clamp(1, PLAYER_COLOURS.len());
+
     let mut grid = Grid::new(WINDOW_WIDTH / TILE_WIDTH, WINDOW_HEIGHT / TILE_HEIGHT)?;
-    let mut timer = 0usize;
+    let seed = replay.as_ref().map_or_else(|| Rng::from_time().next_u64(), |r| r.seed);
+    grid.seed(seed);
+    // creatures get their own random numbers from the same seed, so a replay lets loose the same ones
+    let mut spawner = Rng::new(seed.rotate_left(32));
+    if let Some(replay) = &replay {
+        grid.load_rows(&replay.world)?;
+    }
+    let mut recorder = arg("--record").map(|path| Recorder::create(path, seed, player_count, &grid)).transpose()?;
+    let mut last_frame = Instant::now();
+    // time the grid and the player are behind by, they catch up a tick at a time
+    let mut grid_time = Duration::ZERO;
+    let mut player_time = Duration::ZERO;
 
     // let mut grid: [[TileIndex; width / TILE_WIDTH]; height / TILE_HEIGHT] = ;
+    // the first player's cursor, for the HUD
     let mut cur_x = 0;
     let mut cur_y = 0;
     let mut cur_tile = 1;
     let mut cur_size = 2;
+    let mut cur_boundary = 0;
 
-    let mut player = 
-        Player::new(WINDOW_WIDTH as f32 / 2.0 + 5.0, WINDOW_HEIGHT as f32 / 2.0);
+    // a client's players are whoever the server says, it only has a slot for its own input
+    let mut players = vec![];
+    let mut slots = vec![];
+    if client.is_none() {
+        join(&mut players, &mut slots, player_count);
+    }
+    else {
+        slots.push(Slot::default());
+    }
+    let local = slots.len();
+    for (i, slot) in slots.iter_mut().enumerate() {
+        slot.seat = Some(Seat::for_player(i, local));
+    }
+    // everything else that moves about, ticked and drawn along with the players
+    let mut entities: Vec<Box<dyn Entity>> = vec![];
 
     let mut pause = false;
-    let mut jump = 0.0;
-    let mut run = 0.0;
-    let mut landed_since_jump = false;
+    // the player digs and builds instead of the mouse drawing whatever it likes
+    let mut gameplay = false;
+    let mut show_help = false;
+    // how the replay went once it's over, for the HUD
+    let mut replay_end: Option<String> = None;
+
+    let bindings = Bindings::load(CONTROLS_FILE).unwrap_or_else(|e| {
+        eprintln!("{}, using the default controls", e);
+        Bindings::default()
+    });
 
     canvas.set_draw_color(Color::RGB(0, 255, 255));
     canvas.inner().clear();
@@ -251,7 +655,13 @@ pub fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
         let (tile_width, tile_height) = canvas.get_rel_wh();
 
+        let mods = sdl_context.keyboard().mod_state();
+        let mut pressed = vec![];
+        // presses from the keyboard and mouse, and from controllers, go to whoever has them
+        let keyboard_player = slots.iter().position(|s| s.seat.is_some_and(|s| s.keyboard));
+
         for event in event_pump.poll_iter() {
+            gamepads.handle(&event);
             match event {
                 Event::Window { win_event: WindowEvent::Resized(w, h), .. } => {
                     canvas.w = w as usize;
@@ -264,150 +674,346 @@ pub fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 /*Event::KeyDown { keycode: Some(Keycode::Escape), .. }*/ => {
                     break 'running
                 }
-                
-                Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
+
+                Event::KeyDown { keycode: Some(key), keymod, repeat, .. } => {
+                    if let (Some(action), Some(player)) = (bindings.pressed(Input::Key(key), keymod), keyboard_player) {
+                        pressed.push(Press { action, player, repeat });
+                    }
+                }
+                Event::MouseButtonDown { mouse_btn, .. } => {
+                    if let (Some(action), Some(player)) = (bindings.pressed(Input::Mouse(mouse_btn), mods), keyboard_player) {
+                        pressed.push(Press { action, player, repeat: false });
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    let player = gamepads.slot(which).and_then(|pad| slots.iter().position(|s| s.seat.is_some_and(|s| s.pad == Some(pad))));
+                    if let (Some(action), Some(player)) = (bindings.pressed(Input::Pad(button), mods), player) {
+                        pressed.push(Press { action, player, repeat: false });
+                    }
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    let p = canvas.to_world(x, y);
+                    if let Some(slot) = keyboard_player.and_then(|i| slots.get_mut(i)) {
+                        slot.cursor = Vec2((p.0 as usize / TILE_WIDTH) as f32 + 0.5, (p.1 as usize / TILE_HEIGHT) as f32 + 0.5);
+                    }
+                    // cur_x = x.clamp(0, canvas.w as i32) as usize;
+                    // cur_y = y.clamp(0, canvas.h as i32) as usize;
+                }
+                _ => {}
+            }
+        }
+
+        let kbd = event_pump.keyboard_state();
+        let mouse = event_pump.mouse_state();
+        let held = |slot: &Slot, action| slot.seat.is_some_and(|seat| bindings.held(action, seat, &kbd, &mouse, &gamepads, mods));
+
+        let now = Instant::now();
+        let elapsed = (now - last_frame).min(MAX_CATCH_UP);
+        last_frame = now;
+
+        // each controller's right stick moves its own player's cursor
+        for slot in &mut slots {
+            let stick = slot.seat.and_then(|s| s.pad).map_or(Vec2::ZERO, |pad| gamepads.aim(pad));
+            if stick == Vec2::ZERO { continue; }
+            let cursor = slot.cursor + stick * (CURSOR_STICK_SPEED * elapsed.as_secs_f32());
+            slot.cursor = Vec2(
+                cursor.0.clamp(0.0, (WINDOW_WIDTH / TILE_WIDTH) as f32 - 0.5),
+                cursor.1.clamp(0.0, (WINDOW_HEIGHT / TILE_HEIGHT) as f32 - 0.5));
+        }
+
+        if let Some(server) = &mut server {
+            let count = players.len() + server.accept(players.len(), &grid);
+            join(&mut players, &mut slots, count);
+        }
+
+        // everything from here on only goes by the frame's input, so a replay can stand in for it
+        let mut live = Frame {
+            elapsed,
+            players: slots.iter()
+                .map(|slot| Held {
+                    cursor: (slot.cursor.0 as usize, slot.cursor.1 as usize),
+                    // the keys run flat out, the stick as far as it's pushed
+                    run: (held(slot, Action::RunRight) as i32 as f32 - held(slot, Action::RunLeft) as i32 as f32
+                        + slot.seat.and_then(|s| s.pad).map_or(0.0, |pad| gamepads.movement(pad).0)).clamp(-1.0, 1.0),
+                    actions: HELD_ACTIONS.iter().copied().filter(|&a| held(slot, a)).collect(),
+                })
+                .collect(),
+            pressed,
+        };
+        if let Some(server) = &mut server {
+            server.receive(&grid, gameplay, &mut live);
+        }
+        let frame = match replay.as_mut().map(|r| r.next_frame()) {
+            Some(Some(frame)) => frame,
+            Some(None) => {
+                replay_end = Some(match replay.take().map(|r| r.check(&grid)) {
+                    Some(Err(e)) => {
+                        eprintln!("{}", e);
+                        e
+                    }
+                    _ => "Replay finished".to_string(),
+                });
+                live
+            }
+            None => live,
+        };
+        if let Some(recorder) = &mut recorder {
+            recorder.frame(&frame)?;
+        }
+        if let Some(cursor) = frame.cursor(0) {
+            (cur_x, cur_y) = cursor;
+        }
+
+        // the server does everything for its clients, they only show what it sends back
+        if let Some(client) = &mut client {
+            client.send(&frame)?;
+            client.receive(&mut grid, &mut players, &mut entities)?;
+            gameplay = client.gameplay;
+        }
+        else {
+            join(&mut players, &mut slots, frame.players.len());
+        }
+
+        for &Press { action, player, repeat } in &frame.pressed {
+            if client.is_some() && action != Action::Help { continue; }
+            let (x, y) = frame.cursor(player).unwrap_or((cur_x, cur_y));
+            match action {
+                Action::NextMaterial => {
+                    if gameplay {
+                        players[player].inventory.select_next();
+                        continue;
+                    }
                     cur_tile += 1;
                     cur_tile %= TILES.len();
                 }
-                Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
+                Action::PrevMaterial => {
+                    if gameplay {
+                        players[player].inventory.select_prev();
+                        continue;
+                    }
                     if cur_tile == 0 { cur_tile = TILES.len()-1 }
                     else { cur_tile -= 1; }
                 }
-                // place single tile
-                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
-                    // grid.set(cur_x, cur_y, cur_tile, cur_size);    
-                }
-                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
-                    grid.clear();
-                    player.pos = Vec2(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0);
-                }
-                Event::KeyDown { keycode: Some(Keycode::U), .. } => {
-                    grid.update()?;
-                    player.update(&grid);
-                } 
-                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
-                    pause = !pause;
-                }
-                Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
+                Action::BiggerCursor => {
                     if cur_size == 1 {
                         cur_size = 0;
                     }
                     cur_size += 2;
                     if cur_size >= MAX_CURSOR_SIZE { cur_size = MAX_CURSOR_SIZE; }
                 }
-                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
-                    // FIXME: this is total horsehit idfk who came up with this (me)
+                Action::SmallerCursor => {
                     if cur_size == 1 { cur_size = 3; }
                     cur_size -= 2;
                     if cur_size <= 0 { cur_size = 1; }
                 }
-                /* Event::KeyDown { keycode: Some(keycode), .. } => {
-                    match keycode {
-                        Keycode::Q | Keycode::A => {
-                            player.move_x(-9.0);
-                        }
-                        Keycode::D => {
-                            player.move_x(9.0);
-                        }
-                        _ => ()
+                Action::Secondary if gameplay => {
+                    slots[player].pending.place = Some((x, y));
+                }
+                Action::Detonate => {
+                    grid.explode(x, y, DETONATOR_POWER);
+                }
+                Action::Jump if !repeat => {
+                    slots[player].pending.jump_pressed = true;
+                }
+                Action::Gameplay => {
+                    gameplay = !gameplay;
+                    players.iter_mut().for_each(Player::stop_digging);
+                }
+                Action::Clear => {
+                    grid.clear();
+                    for (i, player) in players.iter_mut().enumerate() {
+                        player.body.pos = spawn_point(i);
                     }
-                } */
-                Event::MouseMotion { x, y, .. } => {
-                    cur_x = (x as f32 / canvas.w as f32 * WINDOW_WIDTH as f32) as usize / TILE_WIDTH;
-                    cur_y = (y as f32 / canvas.h as f32 * WINDOW_HEIGHT as f32) as usize / TILE_HEIGHT;
-                    // cur_x = x.clamp(0, canvas.w as i32) as usize;
-                    // cur_y = y.clamp(0, canvas.h as i32) as usize;
+                    entities.clear();
+                }
+                Action::Step => {
+                    grid.set_obstacles(&entity::obstacles(&players, &entities));
+                    grid.update()?;
+                    entity::shove(&mut players, &mut entities, grid.take_pushes());
+                    entity::tick(&mut players, &mut entities, &mut grid);
+                }
+                Action::Pause => {
+                    pause = !pause;
+                }
+                // fixtures, sand leaks through the diagonal crack, gravel gets stuck on it
+                Action::SandCrack => {
+                    grid.load_diagonal_crack(STONE, SAND);
+                }
+                Action::GravelCrack => {
+                    grid.load_diagonal_crack(STONE, GRAVEL);
+                }
+                Action::CycleMode => {
+                    grid.set_mode(grid.mode().next());
+                }
+                Action::CycleBoundary => {
+                    cur_boundary = (cur_boundary + 1) % boundary::PRESETS.len();
+                    grid.set_boundary(boundary::PRESETS[cur_boundary].1);
+                }
+                // turn whatever's under the cursor into a rigid body, or back into the grid
+                Action::MakeBody => {
+                    grid.make_body(x, y);
+                }
+                // structural integrity, unsupported stone and wood fall down
+                Action::Integrity => {
+                    grid.set_integrity(!grid.integrity());
                 }
+                // checkpoint, where the player comes back when they die
+                Action::Checkpoint => {
+                    players[player].set_checkpoint();
+                }
+                Action::Respawn => {
+                    players[player].respawn();
+                }
+                Action::Help => {
+                    show_help = !show_help;
+                }
+                Action::Throw if !repeat => {
+                    let at = Vec2((x as f32 + 0.5) * TILE_WIDTH as f32, (y as f32 + 0.5) * TILE_HEIGHT as f32);
+                    let thrower = &players[player];
+                    entities.push(Box::new(Projectile::new(thrower.payload, thrower.body.centre(), at)));
+                }
+                Action::NextProjectile => {
+                    players[player].payload = players[player].payload.next();
+                }
+                Action::Critter | Action::Monster => {
+                    let temper = if action == Action::Critter { Temper::Timid } else { Temper::Hostile };
+                    let (px, py) = ((x * TILE_WIDTH) as f32, (y * TILE_HEIGHT) as f32);
+                    entities.push(Box::new(Creature::new(px, py, temper, Rng::new(spawner.next_u64()))));
+                }
+                // held down rather than pressed, see below
                 _ => {}
             }
         }
 
-        let kbd = event_pump.keyboard_state();
-
-        if kbd.is_scancode_pressed(Scancode::D) {
-            player.move_x(run);
-        }
-        if kbd.is_scancode_pressed(Scancode::A) {
-            player.move_x(-run);
-        }
-
-        if kbd.is_scancode_pressed(Scancode::D) || kbd.is_scancode_pressed(Scancode::A) {
-            if run == 0.0 {
-                run = 2.0;
+        // drawing with the mouse isn't part of the simulation, it happens every frame
+        // don't crash if we fail to place a tile, it doesn't really matter
+        // TODO: make this log instead of crash
+        for (i, held) in frame.players.iter().enumerate().filter(|_| !gameplay && client.is_none()) {
+            let (x, y) = held.cursor;
+            if frame.held(i, Action::Primary) {
+                let _ = grid.set(x, y, cur_tile, cur_size);
             }
-            run = run * 2.5;
-            if run > PLAYER_HORIZONTAL_MOVEMENT_SPEED {
-                run = PLAYER_HORIZONTAL_MOVEMENT_SPEED;
+            else if frame.held(i, Action::Secondary) {
+                let _ = grid.set(x, y, 0, cur_size);
             }
         }
-        else {
-            run *= 0.2;
-            if run < 0.001 {
-                run = 0.0;
-            }
+
+        if !pause && client.is_none() {
+            grid_time += frame.elapsed;
+            player_time += frame.elapsed;
         }
 
-        if kbd.is_scancode_pressed(Scancode::Space) {
-            if player.is_grounded(&grid) && landed_since_jump == true {
-                jump = MAXJUMP;
-                landed_since_jump = false;
-                player.move_y(-(MAXJUMP + 2.0));
-            }
-            player.move_y(-jump);
-            // eprintln!("jump: {}", jump);
-            jump = jump / 2.0;
-            if jump < 0.001 {
-                jump = 0.0;
-            }
+        while grid_time >= GRID_STEP {
+            grid.set_obstacles(&entity::obstacles(&players, &entities));
+            grid.update()?;
+            // sand and the like shoving everything about
+            entity::shove(&mut players, &mut entities, grid.take_pushes());
+            grid_time -= GRID_STEP;
         }
-        else {
-            if player.is_grounded(&grid) {
-                landed_since_jump = true;
+
+        while player_time >= PLAYER_STEP {
+            for (i, (player, slot)) in players.iter_mut().zip(&mut slots).enumerate() {
+                let actions = Actions {
+                    run: frame.players.get(i).map_or(0.0, |held| held.run),
+                    jump: frame.held(i, Action::Jump),
+                    dig: if gameplay && frame.held(i, Action::Primary) { frame.cursor(i) } else { None },
+                    ..std::mem::take(&mut slot.pending)
+                };
+                slot.controller.apply(player, &mut grid, &actions);
             }
-        }
 
-        let left = event_pump.mouse_state().left();
-        let right = event_pump.mouse_state().right();
+            let mut all = everyone(&mut players, &mut entities);
+            for explosion in grid.take_explosions() {
+                for entity in &mut all {
+                    let push = explosion.impulse_at(entity.body().centre());
+                    entity.body_mut().push(push);
+                }
+            }
 
-        // don't crash if we fail to place a tile, it doesn't really matter
-        // TODO: make this log instead of crash
-        if left {
-            let _ = grid.set(cur_x, cur_y, cur_tile, cur_size);
+            entity::tick(&mut players, &mut entities, &mut grid);
+            player_time -= PLAYER_STEP;
         }
-        else if right {
-            let _ = grid.set(cur_x, cur_y, 0, cur_size);
+
+        if let Some(server) = &mut server {
+            server.broadcast(&grid, &players, &entities, gameplay);
         }
 
+        // in gameplay the camera closes in on the players, as far as it can with them all in view,
+        // a client only follows its own
+        let mine = client.as_ref().and_then(|c| c.player);
+        let followed: Vec<Rect> = players.iter().enumerate()
+            .filter(|&(i, _)| mine.is_none_or(|mine| mine == i))
+            .map(|(_, p)| p.body.rect())
+            .collect();
+        let target = if gameplay { Camera::fit(&followed) } else { Camera::default() };
+        canvas.camera.follow(target, frame.elapsed);
 
         grid.draw(&mut canvas);
-        player.draw(&mut canvas)?;
-        draw_cursor(cur_x, cur_y, &mut canvas, cur_size);
-        if !pause && timer % SIMULATION_FRAME_DELAY == 0 {
-            grid.update()?; 
-            player.update(&grid);
+        // how far the players are between the last tick and the next one, so they move smoothly
+        // whatever the frame rate, a client's are wherever the server last said
+        let alpha = if client.is_some() { 1.0 } else { player_time.as_secs_f32() / PLAYER_STEP.as_secs_f32() };
+        for entity in everyone(&mut players, &mut entities) {
+            entity.draw(&mut canvas, alpha)?;
+        }
+        for held in &frame.players {
+            draw_cursor(held.cursor.0, held.cursor.1, &mut canvas, if gameplay { 1 } else { cur_size });
         }
         
         let (width, height) = canvas.size();
         
         let canvas = canvas.inner();
 
-        let (mat_texture, mat_target) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &format!("{}", TILES[cur_tile].name), DEFAULT_FONT, 24, TEXT_COLOUR);
+        let (mat_texture, mat_target) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &if gameplay { players.iter().map(|p| format!("{}, {}", p.inventory, p.payload.name())).collect::<Vec<_>>().join(" / ") } else { TILES[cur_tile].name.to_string() }, DEFAULT_FONT, 24, TEXT_COLOUR);
         canvas.copy(&mat_texture, None, Some(mat_target))?;
 
+        let (health_tex, mut health_targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &format!("Health: {}", players.iter().map(|p| p.health().ceil().to_string()).collect::<Vec<_>>().join(" / ")), DEFAULT_FONT, 24, TEXT_COLOUR);
+        health_targ.y = mat_target.height() as i32;
+        canvas.copy(&health_tex, None, Some(health_targ))?;
+
         let (curs_tex, mut curs_targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &format!("Size: {}", cur_size), DEFAULT_FONT, 24, TEXT_COLOUR);
         curs_targ.x = width as i32-curs_targ.width() as i32;
         canvas.copy(&curs_tex, None, Some(curs_targ))?;
 
+        if let Some(end) = &replay_end {
+            let (end_tex, mut end_targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, end, DEFAULT_FONT, 24, TEXT_COLOUR);
+            end_targ.x = width as i32-end_targ.width() as i32;
+            end_targ.y = curs_targ.height() as i32;
+            canvas.copy(&end_tex, None, Some(end_targ))?;
+        }
+
         let (curspos_tex, mut curspos_targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &format!("Pos: ({},{})", cur_x, cur_y), DEFAULT_FONT, 24, TEXT_COLOUR);
         curspos_targ.x = width as i32-curspos_targ.width() as i32;
         curspos_targ.y = height as i32-curspos_targ.height() as i32;
         canvas.copy(&curspos_tex, None, Some(curspos_targ))?;
 
+        let (mode_tex, mut mode_targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &format!("{}, {}{}", grid.mode().name(), boundary::PRESETS[cur_boundary].0, if grid.integrity() { ", collapsing" } else { "" }), DEFAULT_FONT, 24, TEXT_COLOUR);
+        mode_targ.y = height as i32-mode_targ.height() as i32;
+        canvas.copy(&mode_tex, None, Some(mode_targ))?;
+
+
+        if show_help {
+            let lines = bindings.help();
+            canvas.set_draw_color(HELP_BACKGROUND);
+            canvas.fill_rect(Rect::new(HELP_MARGIN, HELP_MARGIN, (width as u32).saturating_sub(2 * HELP_MARGIN as u32), (height as u32).saturating_sub(2 * HELP_MARGIN as u32)))?;
+            let mut y = HELP_MARGIN * 2;
+            for line in lines {
+                let (tex, mut targ) = texture_and_rect_from_str(&ttf_ctx, &texture_creator, &line, DEFAULT_FONT, 16, TEXT_COLOUR);
+                targ.x = HELP_MARGIN * 2;
+                targ.y = y;
+                y += targ.height() as i32;
+                canvas.copy(&tex, None, Some(targ))?;
+            }
+        }
 
         canvas.present();
 
-        timer += 1;
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / FPS));
+        // whatever's left of this frame's share of a second
+        let frame = Duration::new(0, 1_000_000_000u32 / FPS);
+        ::std::thread::sleep(frame.saturating_sub(now.elapsed()));
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish(&grid)?;
     }
     Ok(())
 }
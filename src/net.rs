@@ -0,0 +1,453 @@
+//! Playing together over plain TCP.
+//!
+//! The server plays like it would on its own and owns the grid and every player, each client that
+//! connects gets a player of its own. Clients don't simulate anything. Every frame they send the
+//! server their input, a `replay::Frame` written out as text with only their player in it, and draw
+//! whatever the server sends back: where the players and other entities are and the chunks of the
+//! grid that changed since the frame before, see `grid::chunk`. Someone who's just joined gets
+//! every chunk. Clients only get to do things to their own player, the server ignores them clearing
+//! the world, pausing it and the like.
+//!
+//! Messages both ways are their length as four bytes and then the message. What the server sends
+//! starts with a byte saying which message it is, `WELCOME` or `WORLD`, and numbers are little
+//! endian.
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use sdl2::pixels::Color;
+
+use crate::bindings::Action;
+use crate::entity::{Body, Entity, Puppet};
+use crate::grid::Grid;
+use crate::player::{Player, PLAYER_COLOURS};
+use crate::replay::{Frame, Held, Press};
+use crate::vec2::Vec2;
+
+/// Sent to a client once when it connects, which player it is as a byte, then how many chunks
+/// across and down the grid is as two bytes each.
+const WELCOME           : u8 = 0;
+/// Sent every frame, a byte that's 1 in gameplay mode, a byte for how many players there are and
+/// each one's position and health as floats, two bytes for how many other entities there are and
+/// each one's position as floats, size as two bytes each and colour as three bytes, then two bytes
+/// for how many chunks there are and for each where it is, how long it is and what
+/// `Grid::encode_chunk` made of it.
+const WORLD             : u8 = 1;
+
+/// What a client's allowed to press, the things that only affect their own player. Everything
+/// else changes the whole world or how it runs, which is up to whoever's hosting.
+const REMOTE_ACTIONS    : &[Action] = &[
+    Action::Jump, Action::Secondary, Action::Checkpoint, Action::Respawn, Action::Throw, Action::NextProjectile,
+];
+/// Only allowed in gameplay mode, where they go through the player's own inventory rather than
+/// change what the host draws with.
+const REMOTE_GAMEPLAY_ACTIONS: &[Action] = &[Action::NextMaterial, Action::PrevMaterial];
+
+/// Biggest message either side takes, anything over is someone talking nonsense.
+const MAX_MESSAGE       : usize = 1 << 20;
+/// How much can be waiting to go out to someone before they're too far behind to bother with.
+const MAX_BACKLOG       : usize = MAX_MESSAGE * 4;
+
+fn invalid(why: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, why.into())
+}
+
+/// A connection that never blocks. Messages going out wait in `outbox` until the socket takes
+/// them, ones coming in wait in `inbox` until they're all there.
+struct Connection {
+    stream: TcpStream,
+    inbox: Vec<u8>,
+    outbox: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, inbox: vec![], outbox: vec![] })
+    }
+
+    fn send(&mut self, msg: &[u8]) {
+        self.outbox.extend((msg.len() as u32).to_le_bytes());
+        self.outbox.extend(msg);
+    }
+
+    /// Writes as much as the socket will take.
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outbox.is_empty() {
+            match self.stream.write(&self.outbox) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => { self.outbox.drain(..n); }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if self.outbox.len() > MAX_BACKLOG {
+            return Err(io::Error::new(ErrorKind::TimedOut, "too far behind"));
+        }
+        Ok(())
+    }
+
+    /// Every whole message that's come in since last time.
+    fn receive(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "hung up")),
+                Ok(n) => self.inbox.extend(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut msgs = vec![];
+        while let [a, b, c, d, ..] = self.inbox[..] {
+            let len = u32::from_le_bytes([a, b, c, d]) as usize;
+            if len > MAX_MESSAGE {
+                return Err(invalid("message too big"));
+            }
+            if self.inbox.len() < 4 + len { break; }
+            msgs.push(self.inbox[4..4 + len].to_vec());
+            self.inbox.drain(..4 + len);
+        }
+        Ok(msgs)
+    }
+}
+
+/// Takes numbers off the front of a message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("message cut short"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        let b = self.bytes(4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Somebody playing on the server from somewhere else.
+struct Remote {
+    conn: Connection,
+    player: usize,
+    /// What they're holding down, as of the last input they sent.
+    held: Held,
+    /// What they've pressed since the last frame.
+    pressed: Vec<Press>,
+    /// Hasn't had the whole grid yet.
+    fresh: bool,
+}
+
+pub struct Server {
+    listener: TcpListener,
+    remotes: Vec<Remote>,
+    /// Every chunk as the clients last saw it, row by row.
+    chunks: Vec<Vec<u8>>,
+}
+
+impl Server {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        println!("waiting for players on {}", listener.local_addr()?);
+        Ok(Self { listener, remotes: vec![], chunks: vec![] })
+    }
+
+    /// Lets in whoever's turned up, giving them players from `players` on, and says how many got
+    /// in. There can't be more players than there are colours for them.
+    pub fn accept(&mut self, players: usize, grid: &Grid) -> usize {
+        let mut joined = 0;
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("couldn't let a player in: {}", e);
+                    break;
+                }
+            };
+            let player = players + joined;
+            if player >= PLAYER_COLOURS.len() {
+                eprintln!("turned a player away, the game's full");
+                continue;
+            }
+            let mut conn = match Connection::new(stream) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("couldn't let a player in: {}", e);
+                    continue;
+                }
+            };
+
+            let (cw, ch) = grid.chunks();
+            let mut msg = vec![WELCOME, player as u8];
+            msg.extend((cw as u16).to_le_bytes());
+            msg.extend((ch as u16).to_le_bytes());
+            conn.send(&msg);
+            println!("player {} joined", player + 1);
+            self.remotes.push(Remote { conn, player, held: Held::default(), pressed: vec![], fresh: true });
+            joined += 1;
+        }
+        joined
+    }
+
+    /// Reads what the clients have sent and puts it in `frame` for their players, dropping any
+    /// presses that aren't theirs to make, see `REMOTE_ACTIONS`. Anyone who's gone is forgotten
+    /// about, their player stays where it is.
+    pub fn receive(&mut self, grid: &Grid, gameplay: bool, frame: &mut Frame) {
+        self.remotes.retain_mut(|remote| {
+            let msgs = match remote.conn.receive() {
+                Ok(msgs) => msgs,
+                Err(e) => {
+                    println!("player {} left, {}", remote.player + 1, e);
+                    return false;
+                }
+            };
+            for msg in msgs {
+                let input = std::str::from_utf8(&msg).map_err(|e| e.to_string()).and_then(Frame::parse);
+                let Ok(input) = input.map_err(|e| eprintln!("bad input from player {}, {}", remote.player + 1, e)) else { continue; };
+                if let Some(mut held) = input.players.into_iter().next() {
+                    // a cursor off the grid would have them digging and blowing up who knows what
+                    if grid.index_at(held.cursor.0, held.cursor.1).is_none() {
+                        held.cursor = remote.held.cursor;
+                    }
+                    remote.held = held;
+                }
+                let allowed = |press: &Press| REMOTE_ACTIONS.contains(&press.action)
+                    || (gameplay && REMOTE_GAMEPLAY_ACTIONS.contains(&press.action));
+                remote.pressed.extend(input.pressed.into_iter().filter(allowed).map(|press| Press { player: remote.player, ..press }));
+            }
+            true
+        });
+
+        for remote in &mut self.remotes {
+            if let Some(held) = frame.players.get_mut(remote.player) {
+                *held = remote.held.clone();
+            }
+            frame.pressed.append(&mut remote.pressed);
+        }
+    }
+
+    /// Sends every client where the players and entities are and the chunks that changed since
+    /// last time, or all of them to anyone who's just joined.
+    pub fn broadcast(&mut self, grid: &Grid, players: &[Player], entities: &[Box<dyn Entity>], gameplay: bool) {
+        let (cw, ch) = grid.chunks();
+        let chunks: Vec<Vec<u8>> = (0..cw * ch).map(|i| grid.encode_chunk(i % cw, i / cw)).collect();
+        let changed: Vec<usize> = (0..chunks.len()).filter(|&i| self.chunks.get(i) != Some(&chunks[i])).collect();
+
+        let message = |which: &[usize]| {
+            let mut msg = vec![WORLD, gameplay as u8, players.len() as u8];
+            for player in players {
+                msg.extend(player.body.pos.0.to_le_bytes());
+                msg.extend(player.body.pos.1.to_le_bytes());
+                msg.extend(player.health().to_le_bytes());
+            }
+            msg.extend((entities.len() as u16).to_le_bytes());
+            for entity in entities {
+                let (body, colour) = (entity.body(), entity.colour());
+                msg.extend(body.pos.0.to_le_bytes());
+                msg.extend(body.pos.1.to_le_bytes());
+                msg.extend((body.w as u16).to_le_bytes());
+                msg.extend((body.h as u16).to_le_bytes());
+                msg.extend([colour.r, colour.g, colour.b]);
+            }
+            msg.extend((which.len() as u16).to_le_bytes());
+            for &i in which {
+                msg.extend(((i % cw) as u16).to_le_bytes());
+                msg.extend(((i / cw) as u16).to_le_bytes());
+                msg.extend((chunks[i].len() as u16).to_le_bytes());
+                msg.extend(&chunks[i]);
+            }
+            msg
+        };
+        let delta = message(&changed);
+        let mut full = None;
+        for remote in &mut self.remotes {
+            if remote.fresh {
+                let all: Vec<usize> = (0..chunks.len()).collect();
+                remote.conn.send(full.get_or_insert_with(|| message(&all)));
+                remote.fresh = false;
+            }
+            else {
+                remote.conn.send(&delta);
+            }
+        }
+        self.remotes.retain_mut(|remote| match remote.conn.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                println!("player {} left, {}", remote.player + 1, e);
+                false
+            }
+        });
+        self.chunks = chunks;
+    }
+}
+
+pub struct Client {
+    conn: Connection,
+    /// Whether the server's in gameplay mode.
+    pub gameplay: bool,
+    /// Which of the server's players is this one's, once the server's said.
+    pub player: Option<usize>,
+}
+
+impl Client {
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        let conn = Connection::new(TcpStream::connect(addr)?)?;
+        println!("connected to {}", addr);
+        Ok(Self { conn, gameplay: false, player: None })
+    }
+
+    /// Sends the server this frame's input, which should only have the one player in it.
+    pub fn send(&mut self, frame: &Frame) -> io::Result<()> {
+        self.conn.send(frame.to_string().as_bytes());
+        self.conn.flush()
+    }
+
+    /// Brings `grid`, `players` and `entities` up to date with whatever the server's sent. The
+    /// entities are only ever puppets, the server does all the thinking for them.
+    pub fn receive(&mut self, grid: &mut Grid, players: &mut Vec<Player>, entities: &mut Vec<Box<dyn Entity>>) -> io::Result<()> {
+        for msg in self.conn.receive()? {
+            let mut r = Reader(&msg);
+            match r.u8()? {
+                WELCOME => {
+                    let player = r.u8()? as usize;
+                    if player >= PLAYER_COLOURS.len() {
+                        return Err(invalid("more players than there are colours"));
+                    }
+                    println!("joined as player {}", player + 1);
+                    self.player = Some(player);
+                    if (r.u16()? as usize, r.u16()? as usize) != grid.chunks() {
+                        return Err(invalid("the server's world is a different size"));
+                    }
+                }
+                WORLD => {
+                    self.gameplay = r.u8()? != 0;
+                    let count = r.u8()? as usize;
+                    if count > PLAYER_COLOURS.len() {
+                        return Err(invalid("more players than there are colours"));
+                    }
+                    for i in 0..count {
+                        let (pos, health) = (Vec2(r.f32()?, r.f32()?), r.f32()?);
+                        if players.len() <= i {
+                            players.push(Player::new(pos.0, pos.1, PLAYER_COLOURS[i]));
+                        }
+                        players[i].sync(pos, health);
+                    }
+                    entities.clear();
+                    for _ in 0..r.u16()? {
+                        let pos = Vec2(r.f32()?, r.f32()?);
+                        let (w, h) = (r.u16()? as u32, r.u16()? as u32);
+                        let colour = Color::RGB(r.u8()?, r.u8()?, r.u8()?);
+                        entities.push(Box::new(Puppet { body: Body::new(pos, w, h, 0.0), colour }));
+                    }
+                    for _ in 0..r.u16()? {
+                        let (cx, cy, len) = (r.u16()? as usize, r.u16()? as usize, r.u16()? as usize);
+                        grid.decode_chunk(cx, cy, r.bytes(len)?).map_err(|e| invalid(e.to_string()))?;
+                    }
+                }
+                other => return Err(invalid(format!("unknown message {}", other))),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use crate::{SAND, STONE};
+
+    /// Keeps at it until `done`, things take a moment to get across even on loopback.
+    fn wait(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// A server with one player of its own and a client that's been let in as the second.
+    fn connect(grid: &Grid) -> (Server, Client) {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.listener.local_addr().unwrap().to_string();
+        let client = Client::connect(&addr).unwrap();
+        wait(|| server.accept(1, grid) > 0);
+        (server, client)
+    }
+
+    #[test]
+    fn world() {
+        let mut grid = Grid::new(20, 12).unwrap();
+        grid.set(3, 4, SAND, 1).unwrap();
+        grid.set(19, 11, STONE, 1).unwrap();
+        let players = vec![Player::new(50.0, 60.0, PLAYER_COLOURS[0])];
+        let entities: Vec<Box<dyn Entity>> = vec![Box::new(Puppet { body: Body::new(Vec2(20.0, 30.0), 4, 6, 0.0), colour: Color::RGB(1, 2, 3) })];
+        let (mut server, mut client) = connect(&grid);
+
+        let mut seen = Grid::new(20, 12).unwrap();
+        let (mut seen_players, mut seen_entities) = (vec![], vec![]);
+        server.broadcast(&grid, &players, &entities, true);
+        wait(|| {
+            client.receive(&mut seen, &mut seen_players, &mut seen_entities).unwrap();
+            !seen_players.is_empty()
+        });
+        assert_eq!(client.player, Some(1));
+        assert!(client.gameplay);
+        assert_eq!(seen.to_rows(), grid.to_rows());
+        assert_eq!(seen_players[0].body.pos, players[0].body.pos);
+        assert_eq!(seen_players[0].health(), players[0].health());
+        assert_eq!(seen_entities.len(), 1);
+        assert_eq!(seen_entities[0].body().rect(), entities[0].body().rect());
+        assert_eq!(seen_entities[0].colour(), Color::RGB(1, 2, 3));
+
+        // after that only what's changed
+        grid.set(10, 5, STONE, 1).unwrap();
+        server.broadcast(&grid, &players, &[], false);
+        wait(|| {
+            client.receive(&mut seen, &mut seen_players, &mut seen_entities).unwrap();
+            !client.gameplay
+        });
+        assert_eq!(seen.to_rows(), grid.to_rows());
+        assert!(seen_entities.is_empty());
+    }
+
+    #[test]
+    fn remote_presses() {
+        let grid = Grid::new(20, 12).unwrap();
+        let (mut server, mut client) = connect(&grid);
+        let press = |action| Press { action, player: 0, repeat: false };
+        let input = Frame {
+            players: vec![Held::default()],
+            pressed: vec![press(Action::Clear), press(Action::Jump), press(Action::NextMaterial), press(Action::Detonate)],
+            ..Default::default()
+        };
+        client.send(&input).unwrap();
+
+        let mut frame = Frame { players: vec![Held::default(); 2], ..Default::default() };
+        wait(|| {
+            server.receive(&grid, false, &mut frame);
+            !frame.pressed.is_empty()
+        });
+        // only the jump's theirs to press outside gameplay, and it's their player that jumps
+        assert_eq!(frame.pressed, [Press { action: Action::Jump, player: 1, repeat: false }]);
+    }
+}
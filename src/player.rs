@@ -3,52 +3,89 @@ use super::*;
 const PLAYER_WIDTH      : u32 = TILE_WIDTH as u32;
 const PLAYER_HEIGHT     : u32 = TILE_WIDTH as u32 * 2u32;
 
-const GROUNDED_COLLIDER_WIDTH: u32 = PLAYER_WIDTH;
-const GROUNDED_COLLIDER_HEIGHT: u32 = PLAYER_HEIGHT / 3;
+pub const PLAYER_HORIZONTAL_MOVEMENT_SPEED: f32 = 5.0;
 
-const PLAYER_DECELERATION: f32 = 0.85;
+pub const MAXJUMP: f32 = 6.0;
 
-const GROUND_FRICTION: f32 = 0.65;
-const HORIZ_AIR_DECELERATION: f32 = 0.4;
-const VERT_AIR_DECELERATION: f32 = 0.85;
+/// Tallest ledge the player walks straight up onto instead of stopping at, in pixels.
+const MAX_STEP_UP       : i32 = TILE_HEIGHT as i32;
+/// Ticks after walking off an edge that the player can still jump.
+const COYOTE_TICKS      : u32 = 4;
+/// Ticks a jump pressed just before landing is remembered for.
+const JUMP_BUFFER_TICKS : u32 = 4;
 
-const GRAVITY: f32 = 3.0;
-pub const PLAYER_HORIZONTAL_MOVEMENT_SPEED: f32 = 5.0;
+/// Well under water, so the player bobs up with their head out of it. Any closer and they drift up
+/// too slowly for the collision steps to notice.
+const PLAYER_DENSITY    : f32 = 800.0;
+/// How dense what the player's in has to be, on average, before they swim instead of jumping.
+const SWIM_DENSITY      : f32 = 500.0;
+const SWIM_STROKE       : f32 = 7.0;
+/// Ticks between swim strokes.
+const STROKE_TICKS      : u32 = 6;
 
-pub const MAXJUMP: f32 = 6.0;
+pub const MAX_HEALTH    : f32 = 100.0;
+/// Landing any faster than this hurts, in pixels per tick.
+const SAFE_FALL_SPEED   : f32 = 12.0;
+/// Health lost per pixel per tick over `SAFE_FALL_SPEED`.
+const FALL_DAMAGE       : f32 = 8.0;
+/// Health lost for every tile pressing down on the player while they're stood on something, like
+/// sand piled on top of them or a rigid body that's come down on them, see `Grid::take_pushes`.
+const CRUSH_DAMAGE      : f32 = 2.0;
 
-const PLAYER_COLOUR     : Color = Color::RGB(10, 50, 200);
+/// A colour for each player, there can't be more players than there are colours.
+pub const PLAYER_COLOURS: &[(u8, u8, u8)] = &[(10, 50, 200), (200, 40, 40), (30, 160, 60), (210, 170, 20)];
+const DIG_BAR_COLOUR    : Color = Color::RGB(230, 230, 230);
+
+/// How far away the player can dig and place tiles, in tiles from their centre.
+pub const PLAYER_REACH  : f32 = 4.5;
 
 
 #[derive(Default, Debug)]
 pub struct Player {
-    pub pos: Vec2,
-    vel: Vec2,
-    acc: Vec2
+    pub body: Body,
+    /// Where the player comes back if they get lost or die, see `set_checkpoint`.
+    spawn: Vec2,
+    health: f32,
+    pub inventory: Inventory,
+    /// What the player throws, see `Projectile`.
+    pub payload: Payload,
+    colour: (u8, u8, u8),
+    digging: Option<Dig>,
+    /// Ticks until the player can swim another stroke.
+    stroke_cooldown: u32,
+    /// Ticks left that the player can still jump after being on the ground, see `COYOTE_TICKS`.
+    coyote: u32,
+    /// Ticks left that a jump press is waiting to happen, see `JUMP_BUFFER_TICKS`.
+    jump_buffer: u32,
+    /// How hard tiles have pushed down on the player since the last tick.
+    weight: f32,
 }
 
-impl Player {
-    pub fn new(x: f32, y: f32) -> Self {
-        Self { pos: Vec2(x, y), ..Default::default() }
-    }
+/// A tile the player is part way through digging out.
+#[derive(Debug)]
+struct Dig {
+    at: (usize, usize),
+    /// Ticks it's been dug for so far.
+    ticks: u32,
+    /// Ticks it takes to dig out, from `TileId::dig_time`.
+    time: u32,
+}
 
-    pub fn draw(&self, canvas: &mut Canvas2) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        let rect = Rect::new(self.pos.0 as i32, self.pos.1 as i32, PLAYER_WIDTH, PLAYER_HEIGHT);
-        canvas.set_draw_color(PLAYER_COLOUR);
-        canvas.fill_rect(rect)?;    
-        Ok(())
+impl Player {
+    pub fn new(x: f32, y: f32, colour: (u8, u8, u8)) -> Self {
+        Self { body: Body::new(Vec2(x, y), PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_DENSITY), spawn: Vec2(x, y), health: MAX_HEALTH, colour, ..Default::default() }
     }
 
     fn _debug_intersections(&self, canvas: &mut Canvas2, grid: &Grid) {
-        if let Some(rs) = grid.get_cols_in_rect(self.rect()) {
+        if let Some(rs) = grid.get_cols_in_rect(self.body.rect()) {
             for r in rs {
                 canvas.set_draw_color(DEBUG_DRAW_COLOUR);
                 canvas.fill_rect(r).unwrap();
             }
         }
-        if let Some(cols) = grid.get_cols_in_rect(self.rect()) {
+        if let Some(cols) = grid.get_cols_in_rect(self.body.rect()) {
             for col in cols {
-                let player_rect = self.rect();
+                let player_rect = self.body.rect();
                 let col_obj_rect = col;
 
                 let intersection = player_rect.intersection(col_obj_rect).unwrap();
@@ -59,80 +96,165 @@ impl Player {
         }
     }
 
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.0.ceil() as i32, self.pos.1.ceil() as i32, PLAYER_WIDTH, PLAYER_HEIGHT)
+    /// Whether the player's deep enough in a liquid to swim.
+    pub fn is_swimming(&self, grid: &Grid) -> bool {
+        self.body.medium(grid).density >= SWIM_DENSITY
     }
 
-    pub fn is_grounded(&self, grid: &Grid) -> bool {
-        let col_rect = Rect::new(self.pos.0 as i32 + PLAYER_WIDTH as i32/8, self.pos.1 as i32 + PLAYER_HEIGHT as i32, GROUNDED_COLLIDER_WIDTH - PLAYER_WIDTH/8, GROUNDED_COLLIDER_HEIGHT);
-        if let Some(cols) = grid.get_cols_in_rect(col_rect) {
-            if cols.len() > 0 {
-                true
-            }
-            else { false }
-        }
-        else {
-            false
+    /// Kicks upwards if the player's swimming and has got their breath back from the last
+    /// stroke, returns whether they're swimming at all.
+    pub fn swim(&mut self, grid: &Grid) -> bool {
+        if !self.is_swimming(grid) { return false; }
+        if self.stroke_cooldown == 0 {
+            self.body.vel.1 -= SWIM_STROKE;
+            self.stroke_cooldown = STROKE_TICKS;
         }
+        true
+    }
+
+    /// Asks for a jump, which happens as soon as the player's able to, if that's soon enough.
+    pub fn buffer_jump(&mut self) {
+        self.jump_buffer = JUMP_BUFFER_TICKS;
+    }
+
+    /// Whether a jump's been asked for and the player's on the ground, or was very recently. Uses
+    /// them both up if so, the caller does the actual jumping.
+    pub fn take_jump(&mut self) -> bool {
+        if self.jump_buffer == 0 || self.coyote == 0 { return false; }
+        self.jump_buffer = 0;
+        self.coyote = 0;
+        true
+    }
+
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    /// Puts the player where somebody else worked out they are, the server in a networked game.
+    pub fn sync(&mut self, pos: Vec2, health: f32) {
+        self.body.prev = self.body.pos;
+        self.body.pos = pos;
+        self.health = health;
     }
 
-    pub fn update(&mut self, grid: &Grid) {
-        let prev = self.pos;
-        self.acc.1 = GRAVITY; 
-        self.vel = self.vel + self.acc;
-        self.vel.1 = self.vel.1 * VERT_AIR_DECELERATION;
-        if self.is_grounded(grid) {
-            self.vel.0 *= GROUND_FRICTION;
+    /// Makes wherever the player's standing the place they come back to.
+    pub fn set_checkpoint(&mut self) {
+        self.spawn = self.body.pos;
+    }
+
+    pub fn respawn(&mut self) {
+        self.body.pos = self.spawn;
+        self.body.vel = Vec2::ZERO;
+        self.health = MAX_HEALTH;
+        self.digging = None;
+    }
+
+    pub fn hurt(&mut self, damage: f32) {
+        self.health -= damage;
+        if self.health <= 0.0 {
+            self.respawn();
         }
-        else {
-            self.vel.0 *= HORIZ_AIR_DECELERATION;
+    }
+
+    /// Whether the tile at `(x, y)` is close enough to dig or build on.
+    pub fn in_reach(&self, x: usize, y: usize) -> bool {
+        let tile = Vec2((x as f32 + 0.5) * TILE_WIDTH as f32, (y as f32 + 0.5) * TILE_HEIGHT as f32);
+        tile.dist(&self.body.centre()) <= PLAYER_REACH * TILE_WIDTH as f32
+    }
+
+    /// Keeps digging at `(x, y)`, called every tick the dig button is held. Once the tile's been
+    /// dug for long enough it goes in the inventory. Moving on to another tile starts over.
+    pub fn dig(&mut self, grid: &mut Grid, x: usize, y: usize) {
+        let Some(index) = grid.index_at(x, y) else { return self.stop_digging(); };
+        let id = &TILES[index];
+        let (Some(time), true) = (id.dig_time, id.solid && self.in_reach(x, y)) else {
+            return self.stop_digging();
+        };
+        if self.digging.as_ref().map(|d| d.at) != Some((x, y)) {
+            self.digging = Some(Dig { at: (x, y), ticks: 0, time });
         }
-        self.acc.0 *= PLAYER_DECELERATION;
-
-        self.pos = self.pos + self.vel;
-        'substep: for (i, pt) in Vec2::linef32(prev, self.pos).into_iter().enumerate() {
-            self.pos = pt;
-            if let Some(cols) = grid.get_cols_in_rect(self.rect()) {
-                let len = cols.len();
-                for col in cols {
-                    let player_rect = self.rect();
-                    let col_obj_rect = col;
-
-                    let Some(intersection) = player_rect.intersection(col_obj_rect) else { continue; };
-
-                    let pcentre: Vec2 = player_rect.center().into();
-                    let ccentre = col_obj_rect.center().into();
-
-                    let p_to_obj = pcentre - ccentre;
-                    // eprintln!("{:?}", (ccentre - pcentre));
-                    
-                    let (x_part, y_part) = if intersection.w == intersection.h {
-                        let p_to_obj = prev - self.pos;
-
-                        (p_to_obj.0.signum() * intersection.w as f32, p_to_obj.1.signum() * intersection.h as f32)
-                    } else {
-                        let x_part = if intersection.w < intersection.h { p_to_obj.0.signum() * intersection.w as f32 } else { 0.0 };
-                        let y_part = if intersection.h < intersection.w { p_to_obj.1.signum() * intersection.h as f32 } else { 0.0 };
-                        (x_part, y_part)
-                    };
-
-                    self.pos = self.pos + Vec2(x_part, y_part);
-
-                    if intersection.w < intersection.h { self.vel.0 = 0.0; }
-                    if intersection.h < intersection.w { self.vel.1 = 0.0; }
-                }
-                if len > 0 && i > 1 {
-                    break 'substep;
-                }
+        let Some(dig) = &mut self.digging else { return; };
+        dig.ticks += 1;
+        if dig.ticks >= dig.time {
+            self.digging = None;
+            if grid.set(x, y, AIR, 1).is_ok() {
+                self.inventory.add(index);
             }
         }
     }
 
+    pub fn stop_digging(&mut self) {
+        self.digging = None;
+    }
+
+    /// Puts one of the selected inventory tiles down at `(x, y)`, if it's empty, in reach and not
+    /// where the player's standing.
+    pub fn place(&mut self, grid: &mut Grid, x: usize, y: usize) -> bool {
+        let tile = Rect::new((x * TILE_WIDTH) as i32, (y * TILE_HEIGHT) as i32, TILE_WIDTH as u32, TILE_HEIGHT as u32);
+        if grid.index_at(x, y) != Some(AIR) || !self.in_reach(x, y) || tile.has_intersection(self.body.rect()) {
+            return false;
+        }
+        let Some(index) = self.inventory.take_selected() else { return false; };
+        grid.set(x, y, index, 1).is_ok()
+    }
+
     pub fn move_x(&mut self, acc: f32) {
-        self.vel.0 += acc;
+        self.body.vel.0 += acc;
     }
 
     pub fn move_y(&mut self, acc: f32) {
-        self.vel.1 += acc;
+        self.body.vel.1 += acc;
+    }
+}
+
+impl Entity for Player {
+    fn body(&self) -> &Body {
+        &self.body
+    }
+
+    fn body_mut(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    fn colour(&self) -> Color {
+        self.colour.into()
+    }
+
+    fn shove(&mut self, push: Vec2) {
+        self.body.push(push);
+        self.weight += push.1.max(0.0);
+    }
+
+    /// A tick of physics, bumping into the grid and the entities at `others`, then getting hurt
+    /// by whatever the player's in or landed on.
+    fn update(&mut self, grid: &Grid, others: &[Rect]) {
+        let grounded = self.body.is_grounded(grid, others);
+        self.stroke_cooldown = self.stroke_cooldown.saturating_sub(1);
+        self.jump_buffer = self.jump_buffer.saturating_sub(1);
+        self.coyote = if grounded { COYOTE_TICKS } else { self.coyote.saturating_sub(1) };
+
+        // pushed down onto whatever they're stood on, they're being crushed
+        let weight = std::mem::take(&mut self.weight);
+        let moved = self.body.step(grid, others, if self.coyote > 0 { MAX_STEP_UP } else { 0 });
+        self.hurt(moved.medium.damage + if grounded { weight * CRUSH_DAMAGE } else { 0.0 });
+        if let Some(falling) = moved.landed.filter(|&falling| falling > SAFE_FALL_SPEED) {
+            self.hurt((falling - SAFE_FALL_SPEED) * FALL_DAMAGE);
+        }
+        if moved.lost {
+            self.respawn();
+        }
+    }
+
+    /// The player, and how far along their dig is as a bar along the bottom of the tile.
+    fn draw(&self, canvas: &mut Canvas2, alpha: f32) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        self.body.draw(canvas, alpha, self.colour())?;
+        if let Some(dig) = &self.digging {
+            let (tw, th) = (TILE_WIDTH as u32, TILE_HEIGHT as u32);
+            let done = (dig.ticks * tw / dig.time.max(1)).clamp(1, tw);
+            let (x, y) = (dig.at.0 as u32 * tw, dig.at.1 as u32 * th + th - 2);
+            canvas.set_draw_color(DIG_BAR_COLOUR);
+            canvas.fill_rect(Rect::new(x as i32, y as i32, done, 2))?;
+        }
+        Ok(())
     }
 }
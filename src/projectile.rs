@@ -0,0 +1,168 @@
+//! Things players throw. They fly until they hit something solid or somebody, tracing every
+//! pixel of the way so nothing fast goes through a wall, and then do something to the grid
+//! around where they hit.
+use crate::entity::{Body, Entity};
+use crate::grid::{Grid, TILE_HEIGHT, TILE_WIDTH};
+use crate::vec2::Vec2;
+use crate::{FIRE, WATER};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+const PROJECTILE_SIZE   : u32 = 4;
+/// Ticks a projectile flies for before it goes off wherever it's got to.
+const MAX_FLIGHT        : u32 = 90;
+/// How far a fire bolt's flames reach, in tiles.
+const FIRE_RADIUS       : f32 = 2.0;
+/// How far a water balloon splashes, in tiles.
+const SPLASH_RADIUS     : f32 = 2.5;
+/// Power of the explosion a dig charge makes, see `Grid::explode`.
+const DIG_CHARGE_POWER  : f32 = 6.0;
+
+/// What's thrown, and what it does when it lands.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Payload {
+    /// Bursts into flames, setting light to anything that burns.
+    #[default]
+    FireBolt,
+    /// Splashes water about, which puts fires out.
+    WaterBalloon,
+    /// Blows a hole.
+    DigCharge,
+}
+
+impl Payload {
+    pub const ALL: &'static [Payload] = &[Payload::FireBolt, Payload::WaterBalloon, Payload::DigCharge];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Payload::FireBolt       => "Fire bolt",
+            Payload::WaterBalloon   => "Water balloon",
+            Payload::DigCharge      => "Dig charge",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Payload::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        Payload::ALL[(i + 1) % Payload::ALL.len()]
+    }
+
+    fn colour(self) -> Color {
+        match self {
+            Payload::FireBolt       => Color::RGB(255, 200, 60),
+            Payload::WaterBalloon   => Color::RGB(80, 140, 255),
+            Payload::DigCharge      => Color::RGB(240, 240, 240),
+        }
+    }
+
+    /// How fast it's thrown, in pixels per tick.
+    fn speed(self) -> f32 {
+        match self {
+            Payload::FireBolt       => 14.0,
+            Payload::WaterBalloon   => 9.0,
+            Payload::DigCharge      => 10.0,
+        }
+    }
+
+    /// How much faster it falls every tick, bolts fly near enough straight and balloons get lobbed.
+    fn gravity(self) -> f32 {
+        match self {
+            Payload::FireBolt       => 0.1,
+            Payload::WaterBalloon   => 0.6,
+            Payload::DigCharge      => 0.4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Projectile {
+    body: Body,
+    payload: Payload,
+    /// Ticks it's been flying.
+    ticks: u32,
+    /// Where it hit, in pixels, waiting for `affect` to go off there.
+    hit: Option<Vec2>,
+    /// Gone off, or lost in the void.
+    done: bool,
+}
+
+impl Projectile {
+    /// Thrown from `from` towards `at`, both in pixels.
+    pub fn new(payload: Payload, from: Vec2, at: Vec2) -> Self {
+        let d = at - from;
+        let len = d.dist(&Vec2::ZERO);
+        let dir = if len > 0.0 { d * (1.0 / len) } else { Vec2(0.0, -1.0) };
+        let half = PROJECTILE_SIZE as f32 / 2.0;
+        let mut body = Body::new(from - Vec2(half, half), PROJECTILE_SIZE, PROJECTILE_SIZE, 0.0);
+        body.vel = dir * payload.speed();
+        Self { body, payload, ticks: 0, hit: None, done: false }
+    }
+}
+
+impl Entity for Projectile {
+    fn body(&self) -> &Body {
+        &self.body
+    }
+
+    fn body_mut(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    fn colour(&self) -> Color {
+        self.payload.colour()
+    }
+
+    /// Flies its centre along the line it's going, stopping at the last point before something
+    /// solid or someone it didn't start out inside, which is whoever threw it.
+    fn update(&mut self, grid: &Grid, others: &[Rect]) {
+        if self.hit.is_some() || self.done { return; }
+        let half = Vec2(PROJECTILE_SIZE as f32 / 2.0, PROJECTILE_SIZE as f32 / 2.0);
+        self.body.prev = self.body.pos;
+        self.body.vel.1 += self.payload.gravity();
+        let from = self.body.pos + half;
+        let to = from + self.body.vel;
+        let start = (from.0.floor() as i32, from.1.floor() as i32);
+
+        let mut last = from;
+        for pt in Vec2::linef32(from, to).into_iter().chain([to]) {
+            let (x, y) = (pt.0.floor() as i32, pt.1.floor() as i32);
+            let solid = !grid.get_cols_in_rect(Rect::new(x, y, 1, 1)).unwrap_or_default().is_empty();
+            let struck = others.iter().any(|r| r.contains_point((x, y)) && !r.contains_point(start));
+            if solid || struck {
+                self.hit = Some(last);
+                break;
+            }
+            last = pt;
+        }
+
+        self.body.pos = grid.wrap_point(last - half);
+        self.ticks += 1;
+        if grid.lost_in_void(self.body.rect()) {
+            self.done = true;
+        }
+        else if self.ticks >= MAX_FLIGHT {
+            self.hit = Some(self.body.pos + half);
+        }
+    }
+
+    fn affect(&mut self, grid: &mut Grid) {
+        let Some(at) = self.hit.take() else { return; };
+        self.done = true;
+        let at = grid.wrap_point(at);
+        let (x, y) = ((at.0 / TILE_WIDTH as f32).floor(), (at.1 / TILE_HEIGHT as f32).floor());
+        if x < 0.0 || y < 0.0 || grid.index_at(x as usize, y as usize).is_none() { return; }
+        let (x, y) = (x as usize, y as usize);
+        match self.payload {
+            Payload::FireBolt       => grid.splash(x, y, FIRE_RADIUS, FIRE),
+            Payload::WaterBalloon   => grid.splash(x, y, SPLASH_RADIUS, WATER),
+            Payload::DigCharge      => grid.explode(x, y, DIG_CHARGE_POWER),
+        }
+    }
+
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    fn is_dead(&self) -> bool {
+        self.done
+    }
+}
@@ -0,0 +1,320 @@
+//! Writing down everything that goes into a run so it can be played back exactly, to go with bug
+//! reports.
+//!
+//! The grid and the player only ever see the seed, the world they start in and the input of each
+//! frame, so feeding the same frames back through the main loop ends up in the same world. A
+//! recording is a text file of the seed, how many players there were, the world as
+//! `Grid::to_rows` writes it, a line per frame and a checksum of the world at the end to tell
+//! whether the replay got there too.
+//!
+//! A frame line is how long the frame took in nanoseconds, then for each player after a `|` the
+//! tile under their cursor, how hard they were running and the actions they held down, then
+//! after a last `|` the actions pressed. Those have who pressed them in front and a `*` on the
+//! end if it was a key repeating. Players can turn up part way through, when someone joins over
+//! the network, and then the lines just get longer.
+//!
+//! ```text
+//! seed 1234
+//! players 2
+//! world 79 59
+//! aaaaaaa...
+//! frame 16683412 | 40 31 -1 primary jump | 40 31 0.5 | 0:next_material 1:jump 0:next_material*
+//! end 1f0c5e3a9b2d4c77
+//! ```
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use crate::bindings::Action;
+use crate::grid::Grid;
+use crate::player::PLAYER_COLOURS;
+
+/// The held down actions worth writing down, the rest only do anything when pressed.
+pub const HELD_ACTIONS: &[Action] = &[Action::Primary, Action::Secondary, Action::Jump];
+
+/// What one player was holding down through a frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Held {
+    /// Tile the player's cursor was over.
+    pub cursor: (usize, usize),
+    /// As in `Actions::run`.
+    pub run: f32,
+    /// Which of `HELD_ACTIONS` were held.
+    pub actions: Vec<Action>,
+}
+
+/// An action pressed in a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Press {
+    pub action: Action,
+    /// Which player pressed it.
+    pub player: usize,
+    /// Whether it was a key repeating rather than being pressed again.
+    pub repeat: bool,
+}
+
+/// All the input of one frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub elapsed: Duration,
+    /// What each player held, in player order.
+    pub players: Vec<Held>,
+    pub pressed: Vec<Press>,
+}
+
+fn parse_action(name: &str) -> Result<Action, String> {
+    Action::from_name(name).ok_or(format!("unknown action '{}'", name))
+}
+
+impl Frame {
+    pub fn held(&self, player: usize, action: Action) -> bool {
+        self.players.get(player).is_some_and(|held| held.actions.contains(&action))
+    }
+
+    /// Tile under `player`'s cursor.
+    pub fn cursor(&self, player: usize) -> Option<(usize, usize)> {
+        self.players.get(player).map(|held| held.cursor)
+    }
+
+    /// Reads a frame back from how it's displayed, which is a frame line without the `frame`.
+    /// Anything pressed has to be by one of the players in it.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut sections: Vec<&str> = line.split('|').collect();
+        if sections.len() < 2 { return Err("expected '|'".into()); }
+        let pressed = sections.pop().unwrap_or_default();
+        let elapsed = sections.remove(0).trim().parse().map_err(|_| "bad frame time")?;
+
+        let players = sections.iter()
+            .map(|held| {
+                let mut fields = held.split_whitespace();
+                let mut next = |what| fields.next().ok_or(format!("missing {}", what));
+                let x = next("cursor")?.parse().map_err(|_| "bad cursor")?;
+                let y = next("cursor")?.parse().map_err(|_| "bad cursor")?;
+                let run = next("run")?.parse().map_err(|_| "bad run")?;
+                Ok(Held { cursor: (x, y), run, actions: fields.map(parse_action).collect::<Result<_, _>>()? })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if players.len() > PLAYER_COLOURS.len() {
+            return Err(format!("{} players, there can't be more than {}", players.len(), PLAYER_COLOURS.len()));
+        }
+
+        let pressed = pressed.split_whitespace()
+            .map(|press| {
+                let (player, name) = press.split_once(':').ok_or(format!("expected 'player:action', got '{}'", press))?;
+                let player = player.parse().ok().filter(|&p| p < players.len()).ok_or(format!("bad player '{}'", player))?;
+                let (name, repeat) = match name.strip_suffix('*') {
+                    Some(name) => (name, true),
+                    None => (name, false),
+                };
+                Ok(Press { action: parse_action(name)?, player, repeat })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { elapsed: Duration::from_nanos(elapsed), players, pressed })
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.elapsed.as_nanos())?;
+        for held in &self.players {
+            write!(f, " | {} {} {}", held.cursor.0, held.cursor.1, held.run)?;
+            for action in &held.actions {
+                write!(f, " {}", action.name())?;
+            }
+        }
+        write!(f, " |")?;
+        for press in &self.pressed {
+            write!(f, " {}:{}{}", press.player, press.action.name(), if press.repeat { "*" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path` of a run with `seed` and `players` players from the world in
+    /// `grid`.
+    pub fn create(path: &str, seed: u64, players: usize, grid: &Grid) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let rows = grid.to_rows();
+        writeln!(out, "seed {}", seed)?;
+        writeln!(out, "players {}", players)?;
+        writeln!(out, "world {} {}", rows.first().map_or(0, |r| r.chars().count()), rows.len())?;
+        for row in rows {
+            writeln!(out, "{}", row)?;
+        }
+        Ok(Self { out })
+    }
+
+    pub fn frame(&mut self, frame: &Frame) -> io::Result<()> {
+        writeln!(self.out, "frame {}", frame)
+    }
+
+    /// Ends the recording with where it got to.
+    pub fn finish(mut self, grid: &Grid) -> io::Result<()> {
+        writeln!(self.out, "end {:016x}", grid.checksum())?;
+        self.out.flush()
+    }
+}
+
+pub struct Replay {
+    pub seed: u64,
+    pub players: usize,
+    pub world: Vec<String>,
+    frames: std::vec::IntoIter<Frame>,
+    /// Checksum of the world the recording ended in, if it got to finish.
+    end: Option<u64>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        let mut expect = |what: &str| match lines.next() {
+            Some((n, line)) => line.strip_prefix(what)
+                .map(|rest| (n, rest.trim().to_string()))
+                .ok_or(format!("{}: expected '{}'", n + 1, what)),
+            None => Err(format!("end of file, expected '{}'", what)),
+        };
+
+        let (n, seed) = expect("seed")?;
+        let seed = seed.parse().map_err(|_| format!("{}: bad seed", n + 1))?;
+        let (n, players) = expect("players")?;
+        let players = players.parse().map_err(|_| format!("{}: bad number of players", n + 1))?;
+        let (n, size) = expect("world")?;
+        let (w, h): (usize, usize) = size.split_once(' ')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .ok_or(format!("{}: expected 'world width height'", n + 1))?;
+        let world = (0..h)
+            .map(|_| expect(""))
+            .map(|r| r.and_then(|(n, row)| if row.chars().count() == w { Ok(row) } else {
+                Err(format!("{}: expected a row of {} tiles", n + 1, w))
+            }))
+            .collect::<Result<_, _>>()?;
+
+        let mut frames = vec![];
+        let mut end = None;
+        for (n, line) in lines {
+            let bad = |why: String| format!("{}: {}", n + 1, why);
+            if let Some(frame) = line.strip_prefix("frame ") {
+                let frame = Frame::parse(frame).map_err(bad)?;
+                // the cursors go straight to the grid, which doesn't check them
+                if let Some(held) = frame.players.iter().find(|held| held.cursor.0 >= w || held.cursor.1 >= h) {
+                    return Err(bad(format!("cursor {:?} is off the {}x{} world", held.cursor, w, h)));
+                }
+                frames.push(frame);
+            }
+            else if let Some(hash) = line.strip_prefix("end ") {
+                end = Some(u64::from_str_radix(hash.trim(), 16).map_err(|_| bad("bad checksum".into()))?);
+            }
+            else if !line.trim().is_empty() {
+                return Err(bad(format!("unexpected '{}'", line)));
+            }
+        }
+        Ok(Self { seed, players, world, frames: frames.into_iter(), end })
+    }
+
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        self.frames.next()
+    }
+
+    /// Whether `grid` ended up the way the recording did, once the frames have run out. Always
+    /// fine for a recording that was cut off before it could say.
+    pub fn check(&self, grid: &Grid) -> Result<(), String> {
+        match self.end {
+            Some(end) if end != grid.checksum() => Err(format!(
+                "replay went differently, the world's checksum is {:016x} but the recording's is {:016x}",
+                grid.checksum(), end)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        Frame {
+            elapsed: Duration::from_nanos(16683412),
+            players: vec![
+                Held { cursor: (40, 31), run: -1.0, actions: vec![Action::Primary, Action::Jump] },
+                Held { cursor: (2, 3), run: 0.5, actions: vec![] },
+            ],
+            pressed: vec![
+                Press { action: Action::NextMaterial, player: 0, repeat: false },
+                Press { action: Action::Jump, player: 1, repeat: false },
+                Press { action: Action::NextMaterial, player: 0, repeat: true },
+            ],
+        }
+    }
+
+    /// A recording of a small empty world with `frames` and `end` tacked on.
+    fn recording(frames: &str, end: &str) -> String {
+        let grid = Grid::new(4, 3).unwrap();
+        format!("seed 7\nplayers 1\nworld 4 3\n{}\n{}{}\n", grid.to_rows().join("\n"), frames, end)
+    }
+
+    #[test]
+    fn round_trip() {
+        let line = frame().to_string();
+        assert_eq!(line, "16683412 | 40 31 -1 primary jump | 2 3 0.5 | 0:next_material 1:jump 0:next_material*");
+        assert_eq!(Frame::parse(&line), Ok(frame()));
+        assert_eq!(Frame::parse("5 |"), Ok(Frame { elapsed: Duration::from_nanos(5), ..Frame::default() }));
+    }
+
+    #[test]
+    fn bad_frames() {
+        for line in [
+            "16683412",
+            "soon | 1 2 0 |",
+            "1 | 1 2 |",
+            "1 | 1 -2 0 |",
+            "1 | 1 2 0 fly |",
+            "1 | 1 2 0 | 0:fly",
+            "1 | 1 2 0 | jump",
+            "1 | 1 2 0 | 1:jump",
+        ] {
+            assert!(Frame::parse(line).is_err(), "'{}' parsed", line);
+        }
+        let crowd = format!("1 {}|", " | 0 0 0 ".repeat(PLAYER_COLOURS.len() + 1));
+        assert!(Frame::parse(&crowd).is_err());
+    }
+
+    #[test]
+    fn loads() {
+        let mut replay = Replay::parse(&recording("frame 1 | 3 2 0 |\n", "")).unwrap();
+        assert_eq!((replay.seed, replay.players, replay.world.len()), (7, 1, 3));
+        assert_eq!(replay.next_frame().map(|f| f.cursor(0)), Some(Some((3, 2))));
+        assert_eq!(replay.next_frame(), None);
+    }
+
+    #[test]
+    fn bad_recordings() {
+        assert!(Replay::parse(&recording("frame 1 | 4 2 0 |\n", "")).is_err());
+        assert!(Replay::parse(&recording("frame 1 | 9999 9999 0 |\n", "")).is_err());
+        assert!(Replay::parse(&recording("", "end nonsense")).is_err());
+        assert!(Replay::parse(&recording("", "stuff")).is_err());
+        assert!(Replay::parse("seed 1\nplayers 1\nworld 4 2\n....\n...\n").is_err());
+    }
+
+    #[test]
+    fn checksums() {
+        let grid = Grid::new(4, 3).unwrap();
+        let same = Replay::parse(&recording("", &format!("end {:016x}", grid.checksum()))).unwrap();
+        assert_eq!(same.check(&grid), Ok(()));
+        let different = Replay::parse(&recording("", &format!("end {:016x}", grid.checksum() ^ 1))).unwrap();
+        assert!(different.check(&grid).is_err());
+        let cut_off = Replay::parse(&recording("", "")).unwrap();
+        assert_eq!(cut_off.check(&grid), Ok(()));
+    }
+}
@@ -21,6 +21,12 @@ impl Vec2 {
         (self.0.round() as isize, self.1.round() as isize)
     }
 
+    /// Turns the vector by `angle` radians, clockwise on screen since y points down.
+    pub fn rotate(self, angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self(self.0 * cos - self.1 * sin, self.0 * sin + self.1 * cos)
+    }
+
     pub fn signum(self) -> Self {
         Self(self.0.signum(), self.1.signum())
     }